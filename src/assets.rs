@@ -11,9 +11,10 @@ use bevy::{
     utils::HashMap,
 };
 use bevy_asset_loader::prelude::*;
+use bevy_common_assets::ron::RonAssetPlugin;
 use bevy_rapier3d::prelude::*;
 
-use crate::GameStates;
+use crate::{weapon::WeaponCatalog, GameStates};
 
 /// A collection of assets related to the game environment, such as skybox cubemap texture.
 #[derive(AssetCollection, Resource)]
@@ -37,38 +38,49 @@ pub(crate) struct Models {
     pub(crate) dragoon: Handle<Scene>,
 }
 
+/// A collection of gameplay data assets.
+#[derive(AssetCollection, Resource)]
+pub(crate) struct Catalogs {
+    #[asset(path = "catalog.weapons.ron")]
+    pub(crate) weapons: Handle<WeaponCatalog>,
+}
+
 pub(crate) struct AssetsPlugin;
 impl Plugin for AssetsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_loading_state(
-            LoadingState::new(GameStates::AssetLoading)
-                .continue_to_state(GameStates::Next)
-                .load_collection::<Models>()
-                .load_collection::<Environment>(),
-        )
-        .add_systems(
-            OnEnter(GameStates::AssetLoading),
-            resolve_supported_skybox_image,
-        )
-        .add_systems(
-            OnExit(GameStates::AssetLoading),
-            (fix_png_skybox_metadata, extract_model_colliders),
-        )
-        .init_resource::<ModelColliders>()
-        // From bevy 0.12 scene_spawner runs between Update and PostUpdate so we can set colliders
-        // and setup scene in the same frame scene was spawned
-        .add_systems(PostUpdate, (set_model_collider, setup_scene));
+        app.add_plugins(RonAssetPlugin::<WeaponCatalog>::new(&["weapons.ron"]))
+            .add_loading_state(
+                LoadingState::new(GameStates::AssetLoading)
+                    .continue_to_state(GameStates::Next)
+                    .load_collection::<Models>()
+                    .load_collection::<Environment>()
+                    .load_collection::<Catalogs>(),
+            )
+            .add_systems(
+                OnEnter(GameStates::AssetLoading),
+                resolve_supported_skybox_image,
+            )
+            .add_systems(
+                OnExit(GameStates::AssetLoading),
+                (fix_png_skybox_metadata, extract_model_colliders),
+            )
+            .init_resource::<ModelColliders>()
+            // From bevy 0.12 scene_spawner runs between Update and PostUpdate so we can set colliders
+            // and setup scene in the same frame scene was spawned
+            .add_systems(PostUpdate, (set_model_collider, setup_scene));
     }
 }
 
 fn resolve_supported_skybox_image(
-    render_device: Res<RenderDevice>,
+    // Missing in the headless mode
+    render_device: Option<Res<RenderDevice>>,
     mut dynamic_assets: ResMut<DynamicAssets>,
 ) {
     // Cubemap is generated by https://github.com/petrocket/spacescape, http://alexcpeterson.com/spacescape/
-    let skybox_image = if CompressedImageFormats::from_features(render_device.features())
-        .contains(CompressedImageFormats::ASTC_LDR)
-    {
+    let skybox_image = if render_device.is_some_and(|device| {
+        CompressedImageFormats::from_features(device.features())
+            .contains(CompressedImageFormats::ASTC_LDR)
+    }) {
         // Encoded to ktx2 with ASTC encoding and zstd compression using https://github.com/KhronosGroup/KTX-Software:
         // `toktx --encode astc --astc_blk_d 4x4 --zcmp 19 --cubemap background posx.png negx.png posy.png negy.png posz.png negz.png`
         // This compression saves 50Mb of RAM usage during runtime comparing to the simple PNG.
@@ -0,0 +1,249 @@
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+};
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    faction::{Faction, FriendlyFire},
+    hull::{Hull, HullDestroyed, HullHit},
+    weapon::{
+        hit_entity, weapon_shooter, ProjectileOwner, ShipMotion, Weapon, WeaponType, WeaponTypes,
+    },
+};
+
+/// Catalog entry of a hitscan beam weapon
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct BeamSpec {
+    /// Maximum beam length
+    range: f32,
+    /// Damage dealt to the hit target for each second the beam is held on it
+    damage_per_second: f32,
+    /// Seconds the trigger should be held before the beam appears, zero for instant
+    #[serde(default)]
+    charge_time: f32,
+    /// Visual beam diameter, hits are detected along the beam axis
+    width: f32,
+    /// sRGB color of the unlit beam material
+    color: [f32; 3],
+}
+
+impl BeamSpec {
+    /// Rejects values the beam can't fire with, like a zero range
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.range <= 0.0 {
+            return Err(format!("beam range {} should be positive", self.range));
+        }
+        if self.width <= 0.0 {
+            return Err(format!("beam width {} should be positive", self.width));
+        }
+        if self.damage_per_second < 0.0 {
+            return Err(format!(
+                "beam damage_per_second {} should not be negative",
+                self.damage_per_second
+            ));
+        }
+        if self.charge_time < 0.0 {
+            return Err(format!(
+                "beam charge_time {} should not be negative",
+                self.charge_time
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Beam catalog entry prepared for firing
+pub(crate) struct BeamType {
+    range: f32,
+    damage_per_second: f32,
+    charge_time: f32,
+    /// Cylinder of unit length along Vec3::Y, stretched to the beam length
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl BeamType {
+    pub(crate) fn new(
+        spec: &BeamSpec,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Self {
+        let [red, green, blue] = spec.color;
+        Self {
+            range: spec.range,
+            damage_per_second: spec.damage_per_second,
+            charge_time: spec.charge_time,
+            mesh: meshes.add(Mesh::from(Cylinder::new(spec.width / 2.0, 1.0))),
+            material: materials.add(StandardMaterial {
+                base_color: Color::srgb(red, green, blue),
+                unlit: true,
+                ..default()
+            }),
+        }
+    }
+}
+
+/// Visible part of the beam, a child of the emitting weapon
+#[derive(Component)]
+pub(crate) struct BeamVisual;
+
+/// Charge progress from 0 to 1 and seconds the beam is emitted during a tick of `dt` seconds.
+/// Charging starts over once the trigger is released.
+pub(crate) fn charge_beam(charge: f32, charge_time: f32, is_firing: bool, dt: f32) -> (f32, f32) {
+    if !is_firing {
+        return (0.0, 0.0);
+    }
+    if charge_time <= 0.0 {
+        return (1.0, dt);
+    }
+    let charging = ((1.0 - charge) * charge_time).clamp(0.0, dt);
+    ((charge + dt / charge_time).min(1.0), dt - charging)
+}
+
+/// Local transform of the beam visual emitted forward from the weapon, stretched to the `length`
+fn beam_visual_transform(length: f32) -> Transform {
+    Transform {
+        translation: Vec3::NEG_Z * length / 2.0,
+        rotation: Quat::from_rotation_arc(Vec3::Y, Vec3::NEG_Z),
+        scale: Vec3::new(1.0, length, 1.0),
+    }
+}
+
+/// Casts beams of the firing beam weapons and damages the first hit target.
+/// Runs before the physics step, so the ray is cast against the same state as weapon transforms.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fire_beams(
+    mut commands: Commands,
+    time: Res<Time>,
+    rapier: ReadDefaultRapierContext,
+    weapon_types: Res<WeaponTypes>,
+    friendly_fire: Res<FriendlyFire>,
+    mut weapons: Query<(Entity, &mut Weapon, &GlobalTransform)>,
+    mut visuals: Query<&mut Transform, With<BeamVisual>>,
+    mut hulls: Query<&mut Hull>,
+    ships: Query<ShipMotion>,
+    factions: Query<&Faction>,
+    bodies: Query<(), With<RigidBody>>,
+    parents: Query<&Parent>,
+    mut hull_hits: EventWriter<HullHit>,
+    mut destroyed: EventWriter<HullDestroyed>,
+) {
+    let rapier = rapier.single();
+    for (entity, mut weapon, transform) in weapons.iter_mut() {
+        let Some(WeaponType::Beam(beam)) = weapon_types.get(&weapon) else {
+            continue;
+        };
+        let is_firing = weapon.take_trigger();
+        let emitting;
+        (weapon.charge, emitting) = charge_beam(
+            weapon.charge,
+            beam.charge_time,
+            is_firing,
+            time.delta_secs(),
+        );
+        if emitting <= 0.0 {
+            if let Some(visual) = weapon.beam.take() {
+                commands.entity(visual).despawn_recursive();
+            }
+            continue;
+        }
+
+        let origin = transform.translation();
+        let (shooter, _) = weapon_shooter(entity, origin, &parents, &ships);
+        let owner = ProjectileOwner::new(shooter, &factions);
+        let direction: Vec3 = transform.forward().into();
+        let filter = owner.query_filter(*friendly_fire);
+        let hit = rapier.cast_ray_and_get_normal(origin, direction, beam.range, true, filter);
+        let length = hit.map_or(beam.range, |(_, hit)| hit.time_of_impact);
+
+        if let Some((collider, _)) = hit {
+            let target = hit_entity(collider, &bodies, &parents);
+            if let Ok(mut hull) = hulls.get_mut(target) {
+                let damage = beam.damage_per_second * emitting;
+                let was_destroyed = hull.damage(damage);
+                hull_hits.send(HullHit {
+                    entity: target,
+                    other: shooter.unwrap_or(entity),
+                    damage,
+                });
+                if was_destroyed {
+                    destroyed.send(HullDestroyed { entity: target });
+                }
+            }
+        }
+
+        match weapon.beam.and_then(|visual| visuals.get_mut(visual).ok()) {
+            Some(mut visual) => *visual = beam_visual_transform(length),
+            None => {
+                let visual = commands
+                    .spawn((
+                        Mesh3d(beam.mesh.clone()),
+                        MeshMaterial3d(beam.material.clone()),
+                        beam_visual_transform(length),
+                        NotShadowCaster,
+                        NotShadowReceiver,
+                        BeamVisual,
+                        Name::new("Beam"),
+                    ))
+                    .set_parent(entity)
+                    .id();
+                weapon.beam = Some(visual);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        simulation::DEFAULT_TICK_RATE,
+        weapon::{
+            tests::{hold_triggers, run, ship, target, weapon_app},
+            WeaponFireSet,
+        },
+    };
+
+    #[test]
+    fn beam_deals_damage_per_second_within_range() {
+        let mut app = weapon_app();
+        app.add_systems(FixedUpdate, hold_triggers.before(WeaponFireSet));
+        let world = app.world_mut();
+        world
+            .spawn(ship(Vec3::ZERO))
+            .with_child((Transform::default(), Weapon::new("laser")));
+        let near = world.spawn(target(Vec3::new(0.0, 0.0, -100.0))).id();
+        // Same beam a hundred meters beyond the laser range
+        world
+            .spawn(ship(Vec3::new(50.0, 0.0, 0.0)))
+            .with_child((Transform::default(), Weapon::new("laser")));
+        let far = world.spawn(target(Vec3::new(50.0, 0.0, -500.0))).id();
+
+        let seconds = 1.3;
+        run(&mut app, seconds);
+
+        let world = app.world_mut();
+        let ticks = (seconds * DEFAULT_TICK_RATE as f32) as usize;
+        // Laser deals 40 damage per second after charging for 0.3 seconds
+        let emitted = ticks as f32 / DEFAULT_TICK_RATE as f32 - 0.3;
+        let damage = 1000.0 - world.get::<Hull>(near).unwrap().integrity();
+        assert!(
+            (damage - 40.0 * emitted).abs() < 40.0 / DEFAULT_TICK_RATE as f32,
+            "{damage}"
+        );
+        assert_eq!(world.get::<Hull>(far).unwrap().integrity(), 1000.0);
+
+        // Beams end at the near target surface and at the maximum range
+        let mut lengths: Vec<_> = world
+            .query_filtered::<&Transform, With<BeamVisual>>()
+            .iter(world)
+            .map(|transform| transform.scale.y)
+            .collect();
+        lengths.sort_by(f32::total_cmp);
+        assert_eq!(lengths.len(), 2);
+        assert!((lengths[0] - 97.0).abs() < 1e-3, "{lengths:?}");
+        assert_eq!(lengths[1], 400.0);
+    }
+}
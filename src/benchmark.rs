@@ -0,0 +1,153 @@
+use std::time::{Duration, Instant};
+
+use bevy::{app::AppExit, prelude::*, time::TimeUpdateStrategy};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    projectile::ProjectilePool,
+    simulation::{SimulationRng, TickRate},
+    weapon::{ProjectileOwner, WeaponType, WeaponTypes},
+    GameStates,
+};
+
+/// Gun from the weapon catalog whose projectiles are fired
+const WEAPON: &str = "autocannon";
+/// Projectiles are fired from a sphere of this radius around the world origin
+const SPAWN_RADIUS: f32 = 1000.0;
+/// Projectiles are aimed at random points within this distance from the world origin,
+/// so some of them hit the scene objects
+const AIM_RADIUS: f32 = 200.0;
+/// Simulated seconds measured once the requested number of projectiles is flying
+const MEASURE_SECONDS: u32 = 10;
+
+/// Keeps the requested number of gun projectiles flying and prints how long simulation ticks take.
+/// Expected to be used in a headless app, as time is advanced by exactly one tick per update.
+pub(crate) struct BenchmarkPlugin {
+    pub(crate) projectiles: usize,
+    /// Should match the simulation tick rate
+    pub(crate) tick_rate: u32,
+}
+
+impl Plugin for BenchmarkPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(
+            TickRate(self.tick_rate).duration(),
+        ))
+        .insert_resource(Benchmark {
+            projectiles: self.projectiles,
+            ticks: Vec::new(),
+            tick_start: None,
+            is_measuring: false,
+        })
+        .add_systems(FixedFirst, start_tick.run_if(in_state(GameStates::Next)))
+        .add_systems(
+            FixedUpdate,
+            fire_projectiles
+                .before(PhysicsSet::SyncBackend)
+                .run_if(in_state(GameStates::Next)),
+        )
+        .add_systems(FixedLast, finish_tick.run_if(in_state(GameStates::Next)));
+    }
+}
+
+#[derive(Resource)]
+struct Benchmark {
+    projectiles: usize,
+    /// Durations of the measured ticks
+    ticks: Vec<Duration>,
+    tick_start: Option<Instant>,
+    /// Set once the requested number of projectiles is reached
+    is_measuring: bool,
+}
+
+/// Tops up the flying projectiles, spreading launches over the projectile lifetime
+/// so they don't all expire at the same tick
+fn fire_projectiles(
+    mut commands: Commands,
+    mut benchmark: ResMut<Benchmark>,
+    mut pool: ResMut<ProjectilePool>,
+    mut rng: ResMut<SimulationRng>,
+    weapon_types: Res<WeaponTypes>,
+    time: Res<Time>,
+) {
+    let Some(WeaponType::Gun(gun)) = weapon_types.named(WEAPON) else {
+        panic!("Weapon {WEAPON} is missing in the catalog or is not a gun");
+    };
+    let projectile = gun.projectile();
+    let per_tick =
+        (benchmark.projectiles as f32 * time.delta_secs() / projectile.lifetime()).ceil() as usize;
+    let count = benchmark
+        .projectiles
+        .saturating_sub(pool.live())
+        .min(per_tick);
+    for _ in 0..count {
+        let position = random_unit_vector(&mut rng) * SPAWN_RADIUS;
+        let aim = random_unit_vector(&mut rng) * AIM_RADIUS * rng.next_f32();
+        let direction = (aim - position).normalize();
+        projectile.spawn(
+            &mut commands,
+            &mut pool,
+            position,
+            direction,
+            direction * projectile.speed(),
+            ProjectileOwner::default(),
+        );
+    }
+    if !benchmark.is_measuring && pool.live() >= benchmark.projectiles {
+        info!("{} projectiles are flying, measuring", pool.live());
+        benchmark.is_measuring = true;
+    }
+}
+
+/// Uniformly distributed direction
+fn random_unit_vector(rng: &mut SimulationRng) -> Vec3 {
+    let z = 2.0 * rng.next_f32() - 1.0;
+    let (sin, cos) = (rng.next_f32() * std::f32::consts::TAU).sin_cos();
+    let radius = (1.0 - z * z).sqrt();
+    Vec3::new(radius * cos, radius * sin, z)
+}
+
+fn start_tick(mut benchmark: ResMut<Benchmark>) {
+    benchmark.tick_start = Some(Instant::now());
+}
+
+fn finish_tick(
+    mut benchmark: ResMut<Benchmark>,
+    pool: Res<ProjectilePool>,
+    tick_rate: Res<TickRate>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(start) = benchmark.tick_start.take() else {
+        return;
+    };
+    if !benchmark.is_measuring {
+        return;
+    }
+    benchmark.ticks.push(start.elapsed());
+    if benchmark.ticks.len() < (MEASURE_SECONDS * tick_rate.0) as usize {
+        return;
+    }
+
+    let total: Duration = benchmark.ticks.iter().sum();
+    let mean = total / benchmark.ticks.len() as u32;
+    let max = benchmark.ticks.iter().max().copied().unwrap_or_default();
+    let budget = tick_rate.duration();
+    println!(
+        "Benchmark: {} projectiles requested, {} flying after {} ticks",
+        benchmark.projectiles,
+        pool.live(),
+        benchmark.ticks.len()
+    );
+    println!(
+        "Tick time: mean {:.2?}, max {:.2?}, real time budget {:.2?} ({})",
+        mean,
+        max,
+        budget,
+        if mean <= budget {
+            "sustainable"
+        } else {
+            "too slow"
+        }
+    );
+    exit.send(AppExit::Success);
+}
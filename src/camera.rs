@@ -0,0 +1,289 @@
+use bevy::{
+    input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll},
+    prelude::*,
+    transform::TransformSystem,
+};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    controls::{Action, ActionState},
+    targeting::SelectedTarget,
+    GameStates,
+};
+
+/// Camera offset from the followed entity in its local space: slightly behind and above
+const CHASE_OFFSET: Vec3 = Vec3::new(0.0, 3.0, 20.0);
+/// Angular frequency of the chase camera spring, higher values make camera stiffer
+const CHASE_STIFFNESS: f32 = 8.0;
+/// How fast chase camera turns after the followed entity, in 1/s
+const CHASE_ROTATION_RATE: f32 = 6.0;
+/// Radians per pixel of mouse motion for orbit and free-fly cameras
+const LOOK_SENSITIVITY: f32 = 0.005;
+const FREE_FLY_SPEED: f32 = 50.0;
+
+pub(crate) struct CameraPlugin;
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                switch_camera_mode,
+                switch_camera_target,
+                lock_selected_target,
+            )
+                .chain()
+                .run_if(in_state(GameStates::Next)),
+        )
+        // Runs after simulated transforms are interpolated and before they are propagated,
+        // so camera uses the same positions as rendered entities
+        .add_systems(
+            PostUpdate,
+            update_camera_rig
+                .before(TransformSystem::TransformPropagate)
+                .run_if(in_state(GameStates::Next)),
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) enum CameraMode {
+    /// Behind the followed entity, lagging a bit on rotations
+    #[default]
+    Chase,
+    /// Attached to the [`CockpitAnchor`] of the followed entity
+    Cockpit,
+    /// Rotates around the followed entity with the mouse, ignoring its rotation
+    Orbit,
+    /// Detached debug camera
+    FreeFly,
+    /// Behind the followed entity, looking at the [`SelectedTarget`]
+    TargetLock,
+}
+
+impl CameraMode {
+    fn next(self) -> Self {
+        match self {
+            CameraMode::Chase => CameraMode::Cockpit,
+            CameraMode::Cockpit => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::FreeFly,
+            CameraMode::FreeFly => CameraMode::TargetLock,
+            CameraMode::TargetLock => CameraMode::Chase,
+        }
+    }
+}
+
+/// Marker for the model node (named `camera.*`) where the cockpit camera is placed
+#[derive(Component)]
+pub(crate) struct CockpitAnchor;
+
+/// Camera controller, not a part of any entity hierarchy to be able to follow any entity
+#[derive(Component)]
+pub(crate) struct CameraRig {
+    pub(crate) mode: CameraMode,
+    /// Entity to follow
+    pub(crate) follow: Entity,
+    /// Entity to look at in [`CameraMode::TargetLock`], kept in sync with the [`SelectedTarget`]
+    pub(crate) lock: Option<Entity>,
+    /// Chase camera position relative to the followed entity, in world space.
+    /// Springs to the desired offset instead of the absolute position, so camera doesn't lag on high speeds.
+    offset: Vec3,
+    offset_velocity: Vec3,
+    /// Orbit camera angles and distance
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+impl CameraRig {
+    pub(crate) fn follow(entity: Entity) -> Self {
+        Self {
+            mode: CameraMode::default(),
+            follow: entity,
+            lock: None,
+            offset: CHASE_OFFSET,
+            offset_velocity: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            distance: CHASE_OFFSET.length(),
+        }
+    }
+
+    /// Critically damped spring towards `target` offset. Uses analytical solution,
+    /// so it stays stable even on long frames.
+    fn spring_offset(&mut self, target: Vec3, dt: f32) {
+        let omega = CHASE_STIFFNESS;
+        let x0 = self.offset - target;
+        let v0 = self.offset_velocity;
+        let decay = (-omega * dt).exp();
+        let c = v0 + omega * x0;
+        self.offset = target + (x0 + c * dt) * decay;
+        self.offset_velocity = (v0 - omega * c * dt) * decay;
+    }
+}
+
+/// Ships that camera can follow or lock on
+type Ships<'w, 's> = Query<'w, 's, (Entity, &'static Transform, &'static RigidBody)>;
+
+fn switch_camera_mode(
+    actions: Res<ActionState>,
+    target: Res<SelectedTarget>,
+    ships: Ships,
+    mut rigs: Query<(&mut CameraRig, &Transform)>,
+) {
+    if !actions.just_activated(Action::NextCameraMode) {
+        return;
+    }
+    for (mut rig, transform) in rigs.iter_mut() {
+        rig.mode = rig.mode.next();
+        match rig.mode {
+            CameraMode::Orbit => {
+                // Start orbiting from the current camera position
+                let Ok((_, follow, _)) = ships.get(rig.follow) else {
+                    continue;
+                };
+                let offset = transform.translation - follow.translation;
+                rig.distance = offset.length().max(1.0);
+                rig.yaw = offset.x.atan2(offset.z);
+                rig.pitch = (offset.y / rig.distance).asin();
+            }
+            // Nothing to look at, skip the mode
+            CameraMode::TargetLock if target.0.is_none_or(|target| target == rig.follow) => {
+                rig.mode = rig.mode.next();
+            }
+            _ => {}
+        }
+        info!("Camera mode: {:?}", rig.mode);
+    }
+}
+
+/// Cycles followed entity through all dynamic rigid bodies, so camera can watch NPCs as well
+fn switch_camera_target(actions: Res<ActionState>, ships: Ships, mut rigs: Query<&mut CameraRig>) {
+    if !actions.just_activated(Action::NextCameraTarget) {
+        return;
+    }
+    let mut candidates: Vec<_> = ships
+        .iter()
+        .filter(|(_, _, body)| **body == RigidBody::Dynamic)
+        .map(|(entity, _, _)| entity)
+        .collect();
+    // Stable order regardless of the query iteration order
+    candidates.sort();
+
+    for mut rig in rigs.iter_mut() {
+        let current = candidates.iter().position(|e| *e == rig.follow);
+        let next = current.map_or(0, |i| (i + 1) % candidates.len());
+        if let Some(entity) = candidates.get(next) {
+            rig.follow = *entity;
+        }
+    }
+}
+
+fn lock_selected_target(target: Res<SelectedTarget>, mut rigs: Query<&mut CameraRig>) {
+    for mut rig in rigs.iter_mut() {
+        // Camera can't look at the entity it follows
+        let lock = target.0.filter(|target| *target != rig.follow);
+        if rig.lock != lock {
+            rig.lock = lock;
+        }
+    }
+}
+
+fn update_camera_rig(
+    time: Res<Time>,
+    actions: Res<ActionState>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    children: Query<&Children>,
+    anchors: Query<(), With<CockpitAnchor>>,
+    mut transforms: ParamSet<(TransformHelper, Query<(&mut Transform, &mut CameraRig)>)>,
+) {
+    let dt = time.delta_secs();
+    let look = if actions.active(Action::CameraLook) {
+        mouse_motion.delta * LOOK_SENSITIVITY
+    } else {
+        Vec2::ZERO
+    };
+
+    // Resolve all the required global transforms before mutating cameras
+    let targets: Vec<_> = transforms
+        .p1()
+        .iter()
+        .map(|(_, rig)| (rig.follow, rig.lock))
+        .collect();
+    let targets: Vec<_> = targets
+        .into_iter()
+        .map(|(follow, lock)| {
+            let helper = transforms.p0();
+            let cockpit = children
+                .iter_descendants(follow)
+                .find(|e| anchors.contains(*e))
+                .and_then(|e| helper.compute_global_transform(e).ok());
+            let follow = helper.compute_global_transform(follow).ok();
+            let lock = lock.and_then(|e| helper.compute_global_transform(e).ok());
+            (follow, cockpit, lock)
+        })
+        .collect();
+
+    for ((mut transform, mut rig), (follow, cockpit, lock)) in
+        transforms.p1().iter_mut().zip(targets)
+    {
+        if rig.mode == CameraMode::FreeFly {
+            let mut direction = Vec3::ZERO;
+            for (action, axis) in [
+                (Action::CameraForward, transform.forward()),
+                (Action::CameraBack, transform.back()),
+                (Action::CameraLeft, transform.left()),
+                (Action::CameraRight, transform.right()),
+                (Action::CameraUp, transform.up()),
+                (Action::CameraDown, transform.down()),
+            ] {
+                if actions.active(action) {
+                    direction += *axis;
+                }
+            }
+            transform.translation += direction.normalize_or_zero() * FREE_FLY_SPEED * dt;
+            transform.rotate_y(-look.x);
+            transform.rotate_local_x(-look.y);
+            continue;
+        }
+
+        // Followed entity is gone, keep the camera where it is
+        let Some(follow) = follow.map(|t| t.compute_transform()) else {
+            continue;
+        };
+
+        match (rig.mode, cockpit, lock) {
+            (CameraMode::Cockpit, Some(cockpit), _) => {
+                let cockpit = cockpit.compute_transform();
+                transform.translation = cockpit.translation;
+                transform.rotation = cockpit.rotation;
+            }
+            (CameraMode::Orbit, _, _) => {
+                rig.yaw -= look.x;
+                rig.pitch = (rig.pitch + look.y).clamp(-1.5, 1.5);
+                rig.distance =
+                    (rig.distance * (1.0 - 0.1 * mouse_scroll.delta.y)).clamp(2.0, 500.0);
+
+                let rotation = Quat::from_euler(EulerRot::YXZ, rig.yaw, -rig.pitch, 0.0);
+                transform.translation = follow.translation + rotation * Vec3::Z * rig.distance;
+                transform.look_at(follow.translation, Vec3::Y);
+            }
+            (CameraMode::TargetLock, _, Some(lock)) => {
+                let lock = lock.translation();
+                // Keep followed entity between the camera and the target
+                let direction = (follow.translation - lock).normalize_or(*follow.back());
+                let target = direction * CHASE_OFFSET.z + follow.up() * CHASE_OFFSET.y;
+                rig.spring_offset(target, dt);
+                transform.translation = follow.translation + rig.offset;
+                transform.look_at(lock, follow.up());
+            }
+            // Chase is also a fallback for entities without cockpit or lost lock target
+            _ => {
+                rig.spring_offset(follow.rotation * CHASE_OFFSET, dt);
+                transform.translation = follow.translation + rig.offset;
+                let t = 1.0 - (-CHASE_ROTATION_RATE * dt).exp();
+                transform.rotation = transform.rotation.slerp(follow.rotation, t);
+            }
+        }
+    }
+}
@@ -0,0 +1,832 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bevy::{input::InputSystem, prelude::*, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::TickInput;
+
+/// File with user-defined bindings. Created on the first rebinding and loaded on startup.
+#[cfg(not(target_arch = "wasm32"))]
+const BINDINGS_PATH: &str = "controls.ron";
+
+pub(crate) struct ControlsPlugin;
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Bindings::load())
+            .init_resource::<ActionState>()
+            .init_resource::<Rebinding>()
+            // Actions are resolved right after bevy updates raw input state,
+            // so all systems in `Update` see the same actions during the frame
+            .add_systems(
+                PreUpdate,
+                (capture_rebinding, update_action_state)
+                    .chain()
+                    .after(InputSystem),
+            )
+            // Simulation tick sees the latest frame input, even if several ticks run during the frame
+            .add_systems(FixedPreUpdate, sample_tick_input)
+            .add_systems(Update, controls_window);
+    }
+}
+
+/// Gameplay actions. Simulation systems should check [`TickInput`] instead of raw input devices.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub(crate) enum Action {
+    Accelerate,
+    Decelerate,
+    StrafeLeft,
+    StrafeRight,
+    StrafeUp,
+    StrafeDown,
+    RotateClockwise,
+    RotateCounterClockwise,
+    PrimaryFire,
+    /// Persistent mouse guidance mode, when spaceship follows the cursor
+    MouseGuidance,
+    /// Mouse guidance only while the binding is held
+    ClickGuidance,
+    /// Switch to the next [`crate::camera::CameraMode`]
+    NextCameraMode,
+    /// Make camera follow the next ship
+    NextCameraTarget,
+    /// Rotate orbit and free-fly cameras with the mouse while held
+    CameraLook,
+    CameraForward,
+    CameraBack,
+    CameraLeft,
+    CameraRight,
+    CameraUp,
+    CameraDown,
+    // New actions are appended at the end, as recorded sessions store actions by discriminant
+    /// Select the nearest ship as a target
+    TargetNearest,
+    /// Select the ship closest to the center of the view as a target
+    TargetCrosshair,
+    /// Cycle targets through the hostile ships
+    NextHostileTarget,
+    SecondaryFire,
+    /// Switch the primary weapon group to the next fire mode
+    NextFireMode,
+}
+
+impl Action {
+    pub(crate) const ALL: [Action; 25] = [
+        Action::Accelerate,
+        Action::Decelerate,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::StrafeUp,
+        Action::StrafeDown,
+        Action::RotateClockwise,
+        Action::RotateCounterClockwise,
+        Action::PrimaryFire,
+        Action::MouseGuidance,
+        Action::ClickGuidance,
+        Action::NextCameraMode,
+        Action::NextCameraTarget,
+        Action::CameraLook,
+        Action::CameraForward,
+        Action::CameraBack,
+        Action::CameraLeft,
+        Action::CameraRight,
+        Action::CameraUp,
+        Action::CameraDown,
+        Action::TargetNearest,
+        Action::TargetCrosshair,
+        Action::NextHostileTarget,
+        Action::SecondaryFire,
+        Action::NextFireMode,
+    ];
+}
+
+/// A single button on any of the supported input devices
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub(crate) enum InputButton {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+impl std::fmt::Display for InputButton {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputButton::Key(key) => write!(f, "{key:?}"),
+            InputButton::Mouse(button) => write!(f, "Mouse{button:?}"),
+            InputButton::Gamepad(button) => write!(f, "Gamepad{button:?}"),
+        }
+    }
+}
+
+/// A set of buttons that should be held together to trigger an action, e.g. `ShiftLeft + KeyW`.
+/// Regular single-button binding is just a chord of one button.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) struct Chord(pub(crate) Vec<InputButton>);
+
+impl Chord {
+    fn new(buttons: impl IntoIterator<Item = InputButton>) -> Self {
+        let mut chord = Vec::new();
+        for button in buttons {
+            if !chord.contains(&button) {
+                chord.push(button);
+            }
+        }
+        Self(chord)
+    }
+
+    /// Checks if pressing this chord also completes the `other` one
+    fn contains(&self, other: &Chord) -> bool {
+        other.0.iter().all(|button| self.0.contains(button))
+    }
+}
+
+impl From<KeyCode> for Chord {
+    fn from(key: KeyCode) -> Self {
+        Self::new([InputButton::Key(key)])
+    }
+}
+
+impl From<MouseButton> for Chord {
+    fn from(button: MouseButton) -> Self {
+        Self::new([InputButton::Mouse(button)])
+    }
+}
+
+impl From<GamepadButton> for Chord {
+    fn from(button: GamepadButton) -> Self {
+        Self::new([InputButton::Gamepad(button)])
+    }
+}
+
+impl std::fmt::Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, button) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " + ")?;
+            }
+            write!(f, "{button}")?;
+        }
+        Ok(())
+    }
+}
+
+/// How pressed chord is converted into the active action
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) enum ActivationMode {
+    /// Action is active while any of its chords is held
+    #[default]
+    Hold,
+    /// Action is switched on and off on each press
+    Toggle,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct ActionBinding {
+    pub(crate) mode: ActivationMode,
+    /// Action is triggered by any of these chords
+    pub(crate) chords: Vec<Chord>,
+}
+
+impl ActionBinding {
+    fn hold(chords: impl IntoIterator<Item = Chord>) -> Self {
+        Self {
+            mode: ActivationMode::Hold,
+            chords: chords.into_iter().collect(),
+        }
+    }
+
+    fn toggle(chords: impl IntoIterator<Item = Chord>) -> Self {
+        Self {
+            mode: ActivationMode::Toggle,
+            chords: chords.into_iter().collect(),
+        }
+    }
+}
+
+/// Two actions that are triggered together by the same chord
+#[derive(Debug)]
+pub(crate) struct Conflict {
+    pub(crate) action: Action,
+    pub(crate) other: Action,
+    pub(crate) chord: Chord,
+}
+
+/// Mapping from gameplay actions to the input chords, persisted in [`BINDINGS_PATH`]
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Bindings(BTreeMap<Action, ActionBinding>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use GamepadButton as Pad;
+        Self(BTreeMap::from([
+            (
+                Action::Accelerate,
+                ActionBinding::hold([KeyCode::KeyX.into(), Pad::RightTrigger2.into()]),
+            ),
+            (
+                Action::Decelerate,
+                ActionBinding::hold([KeyCode::KeyZ.into(), Pad::LeftTrigger2.into()]),
+            ),
+            (
+                Action::StrafeLeft,
+                ActionBinding::hold([KeyCode::KeyA.into(), Pad::DPadLeft.into()]),
+            ),
+            (
+                Action::StrafeRight,
+                ActionBinding::hold([KeyCode::KeyD.into(), Pad::DPadRight.into()]),
+            ),
+            (
+                Action::StrafeUp,
+                ActionBinding::hold([KeyCode::KeyW.into(), Pad::DPadUp.into()]),
+            ),
+            (
+                Action::StrafeDown,
+                ActionBinding::hold([KeyCode::KeyS.into(), Pad::DPadDown.into()]),
+            ),
+            (
+                Action::RotateClockwise,
+                ActionBinding::hold([KeyCode::KeyE.into(), Pad::RightTrigger.into()]),
+            ),
+            (
+                Action::RotateCounterClockwise,
+                ActionBinding::hold([KeyCode::KeyQ.into(), Pad::LeftTrigger.into()]),
+            ),
+            (
+                Action::PrimaryFire,
+                ActionBinding::hold([KeyCode::Space.into(), Pad::South.into()]),
+            ),
+            (
+                Action::SecondaryFire,
+                ActionBinding::hold([KeyCode::KeyF.into(), Pad::West.into()]),
+            ),
+            (
+                Action::NextFireMode,
+                ActionBinding::hold([KeyCode::KeyB.into()]),
+            ),
+            (
+                Action::MouseGuidance,
+                ActionBinding::toggle([KeyCode::KeyG.into()]),
+            ),
+            (
+                Action::ClickGuidance,
+                ActionBinding::hold([MouseButton::Left.into()]),
+            ),
+            (
+                Action::NextCameraMode,
+                ActionBinding::hold([KeyCode::KeyC.into(), Pad::Select.into()]),
+            ),
+            (
+                Action::NextCameraTarget,
+                ActionBinding::hold([KeyCode::KeyV.into()]),
+            ),
+            (
+                Action::TargetNearest,
+                ActionBinding::hold([KeyCode::KeyT.into(), Pad::North.into()]),
+            ),
+            (
+                Action::TargetCrosshair,
+                ActionBinding::hold([KeyCode::KeyY.into()]),
+            ),
+            (
+                Action::NextHostileTarget,
+                ActionBinding::hold([KeyCode::KeyH.into(), Pad::East.into()]),
+            ),
+            (
+                Action::CameraLook,
+                ActionBinding::hold([MouseButton::Right.into()]),
+            ),
+            (
+                Action::CameraForward,
+                ActionBinding::hold([KeyCode::ArrowUp.into()]),
+            ),
+            (
+                Action::CameraBack,
+                ActionBinding::hold([KeyCode::ArrowDown.into()]),
+            ),
+            (
+                Action::CameraLeft,
+                ActionBinding::hold([KeyCode::ArrowLeft.into()]),
+            ),
+            (
+                Action::CameraRight,
+                ActionBinding::hold([KeyCode::ArrowRight.into()]),
+            ),
+            (
+                Action::CameraUp,
+                ActionBinding::hold([KeyCode::PageUp.into()]),
+            ),
+            (
+                Action::CameraDown,
+                ActionBinding::hold([KeyCode::PageDown.into()]),
+            ),
+        ]))
+    }
+}
+
+impl Bindings {
+    pub(crate) fn get(&self, action: Action) -> Option<&ActionBinding> {
+        self.0.get(&action)
+    }
+
+    /// Replaces all chords of the `action` with the single `chord`
+    pub(crate) fn rebind(&mut self, action: Action, chord: Chord) {
+        self.0
+            .entry(action)
+            .or_insert_with(|| ActionBinding::hold([]))
+            .chords = vec![chord];
+    }
+
+    /// Finds all pairs of actions where pressing a chord of one action also triggers another one.
+    /// Chords that only partially overlap (like `ShiftLeft + KeyW` and `ControlLeft + KeyW`) are fine.
+    pub(crate) fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        for (action, binding) in &self.0 {
+            for (other, other_binding) in &self.0 {
+                if action >= other {
+                    continue;
+                }
+                for chord in binding.chords.iter().filter(|c| !c.0.is_empty()) {
+                    for other_chord in other_binding.chords.iter().filter(|c| !c.0.is_empty()) {
+                        if chord.contains(other_chord) || other_chord.contains(chord) {
+                            conflicts.push(Conflict {
+                                action: *action,
+                                other: *other,
+                                chord: chord.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Loads bindings from [`BINDINGS_PATH`], falling back to defaults for missing actions
+    fn load() -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        let bindings = Self::load_from(std::path::Path::new(BINDINGS_PATH));
+        #[cfg(target_arch = "wasm32")]
+        let bindings = Self::default();
+
+        for conflict in bindings.conflicts() {
+            warn!(
+                "{:?} and {:?} are both triggered by {}",
+                conflict.action, conflict.other, conflict.chord
+            );
+        }
+        bindings
+    }
+
+    /// Defaults overridden by the bindings saved at the `path`, if the file exists and is valid
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_from(path: &std::path::Path) -> Self {
+        let mut bindings = Self::default();
+        let path_name = path.display();
+        match std::fs::read_to_string(path) {
+            Ok(text) => match ron::from_str::<Bindings>(&text) {
+                // Keep defaults for actions that were added after the file was saved
+                Ok(loaded) => bindings.0.extend(loaded.0),
+                Err(err) => warn!("Failed to parse {path_name}, using default bindings: {err}"),
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => warn!("Failed to read {path_name}, using default bindings: {err}"),
+        }
+        bindings
+    }
+
+    fn save(&self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.save_to(std::path::Path::new(BINDINGS_PATH));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_to(&self, path: &std::path::Path) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(path, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            warn!("Failed to save bindings to {}: {err}", path.display());
+        }
+    }
+}
+
+/// Current state of all actions, updated once per frame in `PreUpdate`.
+/// Simulation uses [`TickInput`] sampled from it, while visual-only systems like camera can use it directly.
+#[derive(Resource, Default)]
+pub(crate) struct ActionState {
+    /// Actions with at least one chord held during the current frame
+    pressed: BTreeSet<Action>,
+    /// Actions that are on, according to their [`ActivationMode`]
+    active: BTreeSet<Action>,
+    /// Actions that were switched on during the current frame
+    just_activated: BTreeSet<Action>,
+    /// Cursor offset from the primary window center, if cursor is inside the window
+    cursor_offset: Option<Vec2>,
+}
+
+impl ActionState {
+    pub(crate) fn active(&self, action: Action) -> bool {
+        self.active.contains(&action)
+    }
+
+    pub(crate) fn just_activated(&self, action: Action) -> bool {
+        self.just_activated.contains(&action)
+    }
+
+    /// Updates state from the set of pressed actions during the current frame
+    fn update(&mut self, bindings: &Bindings, pressed: BTreeSet<Action>) {
+        self.just_activated.clear();
+        for action in Action::ALL {
+            let was_pressed = self.pressed.contains(&action);
+            let is_pressed = pressed.contains(&action);
+            let mode = bindings.get(action).map(|b| b.mode).unwrap_or_default();
+
+            let was_active = self.active.contains(&action);
+            let is_active = match mode {
+                ActivationMode::Hold => is_pressed,
+                ActivationMode::Toggle => was_active ^ (is_pressed && !was_pressed),
+            };
+
+            if is_active {
+                if !was_active {
+                    self.just_activated.insert(action);
+                }
+                self.active.insert(action);
+            } else {
+                self.active.remove(&action);
+            }
+        }
+        self.pressed = pressed;
+    }
+}
+
+/// Raw input devices, combined to check buttons regardless of the device type
+#[derive(bevy::ecs::system::SystemParam)]
+struct InputButtons<'w, 's> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    gamepads: Query<'w, 's, &'static Gamepad>,
+    egui: EguiContexts<'w, 's>,
+}
+
+impl InputButtons<'_, '_> {
+    /// Checks if mouse is interacting with egui windows so mouse buttons should be ignored
+    fn egui_has_pointer(&mut self) -> bool {
+        let ctx = self.egui.ctx_mut();
+        ctx.is_pointer_over_area() || ctx.is_using_pointer()
+    }
+
+    fn pressed(&self, button: InputButton, ignore_mouse: bool) -> bool {
+        match button {
+            InputButton::Key(key) => self.keys.pressed(key),
+            InputButton::Mouse(button) => !ignore_mouse && self.mouse.pressed(button),
+            InputButton::Gamepad(button) => self.gamepads.iter().any(|g| g.pressed(button)),
+        }
+    }
+
+    /// Returns the first button pressed during the current frame, if any
+    fn just_pressed(&self) -> Option<InputButton> {
+        let key = self.keys.get_just_pressed().next().copied();
+        let mouse = self.mouse.get_just_pressed().next().copied();
+        let gamepad = self
+            .gamepads
+            .iter()
+            .find_map(|g| g.get_just_pressed().next().copied());
+
+        key.map(InputButton::Key)
+            .or(mouse.map(InputButton::Mouse))
+            .or(gamepad.map(InputButton::Gamepad))
+    }
+}
+
+fn update_action_state(
+    bindings: Res<Bindings>,
+    rebinding: Res<Rebinding>,
+    mut state: ResMut<ActionState>,
+    mut buttons: InputButtons,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    state.cursor_offset = windows.get_single().ok().and_then(|window| {
+        let center = Vec2::new(window.width() / 2.0, window.height() / 2.0);
+        window.cursor_position().map(|pos| center - pos)
+    });
+
+    // Don't trigger actions by the input that is going to be assigned
+    if rebinding.0.is_some() {
+        state.update(&bindings, BTreeSet::new());
+        return;
+    }
+
+    let ignore_mouse = buttons.egui_has_pointer();
+    let pressed = bindings
+        .0
+        .iter()
+        .filter(|(_, binding)| {
+            binding.chords.iter().any(|chord| {
+                !chord.0.is_empty() && chord.0.iter().all(|b| buttons.pressed(*b, ignore_mouse))
+            })
+        })
+        .map(|(action, _)| *action)
+        .collect();
+    state.update(&bindings, pressed);
+}
+
+fn sample_tick_input(state: Res<ActionState>, mut input: ResMut<TickInput>) {
+    *input = TickInput::new(state.active.iter().copied(), state.cursor_offset);
+}
+
+/// Action that waits for a new chord to be pressed
+#[derive(Resource, Default)]
+struct Rebinding(Option<Action>);
+
+const MODIFIER_KEYS: [KeyCode; 6] = [
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+];
+
+/// Assigns the first pressed button (with held modifiers, if any) to the action being rebound
+fn capture_rebinding(
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<Bindings>,
+    mut buttons: InputButtons,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+    if buttons.keys.just_pressed(KeyCode::Escape) {
+        rebinding.0 = None;
+        return;
+    }
+    let Some(button) = buttons.just_pressed() else {
+        return;
+    };
+    // Modifiers become a part of the chord only once another button is pressed
+    if matches!(button, InputButton::Key(key) if MODIFIER_KEYS.contains(&key)) {
+        return;
+    }
+    // Click on the "Rebind" button itself should not be captured
+    if matches!(button, InputButton::Mouse(_)) && buttons.egui_has_pointer() {
+        return;
+    }
+
+    let modifiers = MODIFIER_KEYS
+        .into_iter()
+        .filter(|key| buttons.keys.pressed(*key))
+        .map(InputButton::Key);
+    bindings.rebind(action, Chord::new(modifiers.chain([button])));
+    bindings.save();
+    rebinding.0 = None;
+}
+
+/// Window with the list of bindings and buttons to change them
+fn controls_window(
+    mut egui: EguiContexts,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    let conflicts = bindings.conflicts();
+    let mut changed = false;
+
+    egui::Window::new("Controls")
+        .default_open(false)
+        .show(egui.ctx_mut(), |ui| {
+            egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+                for action in Action::ALL {
+                    ui.label(format!("{action:?}"));
+
+                    let chords = bindings
+                        .get(action)
+                        .map(|binding| {
+                            let chords: Vec<_> =
+                                binding.chords.iter().map(|c| c.to_string()).collect();
+                            chords.join(", ")
+                        })
+                        .unwrap_or_default();
+                    if conflicts
+                        .iter()
+                        .any(|c| c.action == action || c.other == action)
+                    {
+                        ui.colored_label(egui::Color32::RED, chords);
+                    } else {
+                        ui.label(chords);
+                    }
+
+                    if let Some(binding) = bindings.0.get_mut(&action) {
+                        let mut toggle = binding.mode == ActivationMode::Toggle;
+                        if ui.checkbox(&mut toggle, "toggle").changed() {
+                            binding.mode = if toggle {
+                                ActivationMode::Toggle
+                            } else {
+                                ActivationMode::Hold
+                            };
+                            changed = true;
+                        }
+                    } else {
+                        ui.label("");
+                    }
+
+                    if rebinding.0 == Some(action) {
+                        ui.label("Press a button (Esc to cancel)");
+                    } else if ui.button("Rebind").clicked() {
+                        rebinding.0 = Some(action);
+                    }
+                    ui.end_row();
+                }
+            });
+
+            for conflict in &conflicts {
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!(
+                        "{:?} and {:?} are both triggered by {}",
+                        conflict.action, conflict.other, conflict.chord
+                    ),
+                );
+            }
+        });
+
+    if changed {
+        bindings.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(actions: impl IntoIterator<Item = (Action, ActionBinding)>) -> Bindings {
+        Bindings(actions.into_iter().collect())
+    }
+
+    fn chord(keys: impl IntoIterator<Item = KeyCode>) -> Chord {
+        Chord::new(keys.into_iter().map(InputButton::Key))
+    }
+
+    #[test]
+    fn default_bindings_have_no_conflicts() {
+        let conflicts = Bindings::default().conflicts();
+        assert!(conflicts.is_empty(), "{conflicts:?}");
+    }
+
+    #[test]
+    fn chord_contained_in_another_conflicts() {
+        let conflicts = bindings([
+            (
+                Action::StrafeUp,
+                ActionBinding::hold([chord([KeyCode::KeyW])]),
+            ),
+            (
+                Action::CameraUp,
+                ActionBinding::hold([chord([KeyCode::ShiftLeft, KeyCode::KeyW])]),
+            ),
+            // Shares only a part of the chord above
+            (
+                Action::CameraDown,
+                ActionBinding::hold([chord([KeyCode::ControlLeft, KeyCode::KeyW])]),
+            ),
+            (
+                Action::StrafeDown,
+                ActionBinding::hold([chord([KeyCode::KeyS])]),
+            ),
+        ])
+        .conflicts();
+
+        let pairs: Vec<_> = conflicts.iter().map(|c| (c.action, c.other)).collect();
+        assert_eq!(
+            pairs,
+            [
+                (Action::StrafeUp, Action::CameraUp),
+                (Action::StrafeUp, Action::CameraDown),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_chord_conflicts() {
+        let conflicts = bindings([
+            (
+                Action::PrimaryFire,
+                ActionBinding::hold([KeyCode::Space.into()]),
+            ),
+            (
+                Action::SecondaryFire,
+                ActionBinding::toggle([KeyCode::KeyF.into(), KeyCode::Space.into()]),
+            ),
+        ])
+        .conflicts();
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].chord, KeyCode::Space.into());
+    }
+
+    fn update(state: &mut ActionState, bindings: &Bindings, pressed: &[Action]) {
+        state.update(bindings, pressed.iter().copied().collect());
+    }
+
+    #[test]
+    fn hold_action_is_active_while_pressed() {
+        let bindings = Bindings::default();
+        let mut state = ActionState::default();
+        let action = Action::PrimaryFire;
+
+        update(&mut state, &bindings, &[action]);
+        assert!(state.active(action) && state.just_activated(action));
+        update(&mut state, &bindings, &[action]);
+        assert!(state.active(action) && !state.just_activated(action));
+        update(&mut state, &bindings, &[]);
+        assert!(!state.active(action) && !state.just_activated(action));
+    }
+
+    #[test]
+    fn toggle_action_switches_on_each_press() {
+        let bindings = Bindings::default();
+        let mut state = ActionState::default();
+        let action = Action::MouseGuidance;
+        assert_eq!(bindings.get(action).unwrap().mode, ActivationMode::Toggle);
+
+        update(&mut state, &bindings, &[action]);
+        assert!(state.active(action) && state.just_activated(action));
+        // Stays on while held and after release
+        update(&mut state, &bindings, &[action]);
+        assert!(state.active(action) && !state.just_activated(action));
+        update(&mut state, &bindings, &[]);
+        assert!(state.active(action));
+        // Next press switches it off
+        update(&mut state, &bindings, &[action]);
+        assert!(!state.active(action) && !state.just_activated(action));
+        update(&mut state, &bindings, &[]);
+        assert!(!state.active(action));
+    }
+
+    /// Path in the system temporary directory, unique for each test
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("outer-frontiers-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn saved_bindings_are_loaded() {
+        let path = temp_path("saved.controls.ron");
+        let mut saved = Bindings::default();
+        let rebound = chord([KeyCode::ShiftLeft, KeyCode::KeyR]);
+        saved.rebind(Action::PrimaryFire, rebound.clone());
+        saved.0.get_mut(&Action::ClickGuidance).unwrap().mode = ActivationMode::Toggle;
+        saved.save_to(&path);
+
+        let loaded = Bindings::load_from(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        for action in Action::ALL {
+            let (saved, loaded) = (saved.get(action).unwrap(), loaded.get(action).unwrap());
+            assert_eq!(saved.mode, loaded.mode, "{action:?}");
+            assert_eq!(saved.chords, loaded.chords, "{action:?}");
+        }
+        assert_eq!(loaded.get(Action::PrimaryFire).unwrap().chords, [rebound]);
+    }
+
+    #[test]
+    fn missing_or_invalid_file_falls_back_to_defaults() {
+        let defaults = Bindings::default();
+        let invalid = temp_path("invalid.controls.ron");
+        std::fs::write(&invalid, "{ PrimaryFire: ").unwrap();
+
+        for path in [temp_path("missing.controls.ron"), invalid.clone()] {
+            let loaded = Bindings::load_from(&path);
+            for action in Action::ALL {
+                assert_eq!(
+                    loaded.get(action).unwrap().chords,
+                    defaults.get(action).unwrap().chords,
+                    "{action:?}"
+                );
+            }
+        }
+        std::fs::remove_file(&invalid).unwrap();
+    }
+
+    #[test]
+    fn actions_missing_in_file_keep_defaults() {
+        let path = temp_path("partial.controls.ron");
+        std::fs::write(
+            &path,
+            "({ PrimaryFire: (mode: Toggle, chords: [([Key(KeyR)])]) })",
+        )
+        .unwrap();
+
+        let loaded = Bindings::load_from(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let fire = loaded.get(Action::PrimaryFire).unwrap();
+        assert_eq!(fire.mode, ActivationMode::Toggle);
+        assert_eq!(fire.chords, [KeyCode::KeyR.into()]);
+        assert_eq!(
+            loaded.get(Action::SecondaryFire).unwrap().chords,
+            Bindings::default()
+                .get(Action::SecondaryFire)
+                .unwrap()
+                .chords
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    hull::{destroy_ships, Hull, HullDestroyed, HullHit},
+    weapon::{apply_projectile_hits, center_of_mass, hit_entity, lifetime},
+    GameStates,
+};
+
+/// Explosions damage and push every body within their radius.
+/// Explosive missiles explode on hit and explosive ships once destroyed, see [`Explosive`].
+pub(crate) struct ExplosionPlugin;
+impl Plugin for ExplosionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Explosion>().add_systems(
+            FixedUpdate,
+            resolve_explosions
+                .after(apply_projectile_hits)
+                .after(destroy_ships)
+                .after(lifetime)
+                .run_if(in_state(GameStates::Next)),
+        );
+    }
+}
+
+/// How the explosion effect weakens from the center to the edge
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) enum Falloff {
+    /// Full effect within the whole radius
+    Constant,
+    #[default]
+    Linear,
+    /// Drops quickly near the center, like a pressure wave in the open
+    Quadratic,
+}
+
+impl Falloff {
+    /// Fraction of the full effect at the `distance` from the center of an explosion with the `radius`
+    pub(crate) fn scale(self, distance: f32, radius: f32) -> f32 {
+        if distance > radius || radius <= 0.0 {
+            return 0.0;
+        }
+        let remaining = 1.0 - distance / radius;
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => remaining,
+            Falloff::Quadratic => remaining * remaining,
+        }
+    }
+}
+
+/// Catalog entry of an explosion, e.g. of a missile warhead
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub(crate) struct ExplosionSpec {
+    radius: f32,
+    /// Damage at the center
+    damage: f32,
+    /// Impulse pushing bodies away from the center, at the center
+    impulse: f32,
+    #[serde(default)]
+    falloff: Falloff,
+    /// Bodies behind other objects are protected from the explosion
+    #[serde(default)]
+    occlusion: bool,
+}
+
+impl ExplosionSpec {
+    pub(crate) const fn new(radius: f32, damage: f32, impulse: f32) -> Self {
+        Self {
+            radius,
+            damage,
+            impulse,
+            falloff: Falloff::Linear,
+            occlusion: false,
+        }
+    }
+
+    pub(crate) fn with_falloff(mut self, falloff: Falloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    pub(crate) fn with_occlusion(mut self) -> Self {
+        self.occlusion = true;
+        self
+    }
+}
+
+/// Entity explodes when it hits something or its [`crate::weapon::Lifetime`] runs out, for projectiles,
+/// or once destroyed, for ships
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct Explosive(pub(crate) ExplosionSpec);
+
+/// Explosion happened at the `center`
+#[derive(Event, Clone, Debug)]
+pub(crate) struct Explosion {
+    pub(crate) center: Vec3,
+    pub(crate) spec: ExplosionSpec,
+    /// Entity reported as the cause of the hull damage, e.g. the ship that fired the missile.
+    /// It is not affected by the explosion itself.
+    pub(crate) cause: Entity,
+}
+
+/// Body in range of an explosion
+struct BlastHit {
+    /// Closest point of the body to the center
+    point: Vec3,
+    distance: f32,
+}
+
+/// Finds bodies intersecting the explosion sphere, then damages and pushes each of them
+/// according to the distance from the center to its closest point.
+/// Runs after the physics step, the impulse is applied during the next one.
+#[allow(clippy::too_many_arguments)]
+fn resolve_explosions(
+    rapier: ReadDefaultRapierContext,
+    mut explosions: EventReader<Explosion>,
+    colliders: Query<(&Collider, &GlobalTransform)>,
+    mut hulls: Query<&mut Hull>,
+    mut bodies: Query<(
+        &GlobalTransform,
+        Option<&ReadMassProperties>,
+        &mut ExternalImpulse,
+    )>,
+    rigid_bodies: Query<(), With<RigidBody>>,
+    parents: Query<&Parent>,
+    mut hull_hits: EventWriter<HullHit>,
+    mut destroyed: EventWriter<HullDestroyed>,
+) {
+    let rapier = rapier.single();
+    for explosion in explosions.read() {
+        let spec = explosion.spec;
+        let center = explosion.center;
+        // Missiles and other projectiles are sensors, they are not affected
+        let filter = QueryFilter::default()
+            .exclude_sensors()
+            .exclude_rigid_body(explosion.cause);
+
+        // Bodies can have several colliders, the closest one counts. Ordered by entity to keep the simulation deterministic.
+        let mut hits: BTreeMap<Entity, BlastHit> = BTreeMap::new();
+        rapier.intersections_with_shape(
+            center,
+            Quat::IDENTITY,
+            &Collider::ball(spec.radius),
+            filter,
+            |collider| {
+                let target = hit_entity(collider, &rigid_bodies, &parents);
+                let Ok((shape, transform)) = colliders.get(collider) else {
+                    return true;
+                };
+                let (_, rotation, translation) = transform.to_scale_rotation_translation();
+                let projection = shape.project_point(translation, rotation, center, true);
+                let distance = projection.point.distance(center);
+                if hits.get(&target).is_none_or(|hit| distance < hit.distance) {
+                    hits.insert(
+                        target,
+                        BlastHit {
+                            point: projection.point,
+                            distance,
+                        },
+                    );
+                }
+                true
+            },
+        );
+
+        for (target, hit) in hits {
+            if spec.occlusion
+                && is_occluded(
+                    rapier,
+                    center,
+                    &hit,
+                    target,
+                    filter,
+                    &rigid_bodies,
+                    &parents,
+                )
+            {
+                continue;
+            }
+            let scale = spec.falloff.scale(hit.distance, spec.radius);
+            if scale <= 0.0 {
+                continue;
+            }
+
+            if let Ok(mut hull) = hulls.get_mut(target) {
+                let damage = spec.damage * scale;
+                let was_destroyed = hull.damage(damage);
+                hull_hits.send(HullHit {
+                    entity: target,
+                    other: explosion.cause,
+                    damage,
+                });
+                if was_destroyed {
+                    destroyed.send(HullDestroyed { entity: target });
+                }
+            }
+            if let Ok((transform, mass, mut impulse)) = bodies.get_mut(target) {
+                let body_center = center_of_mass(transform, mass);
+                // Body engulfed by the explosion is pushed away from the center through its center of mass
+                let direction = (hit.point - center)
+                    .try_normalize()
+                    .unwrap_or_else(|| (body_center - center).normalize_or_zero());
+                *impulse += ExternalImpulse::at_point(
+                    direction * spec.impulse * scale,
+                    hit.point,
+                    body_center,
+                );
+            }
+        }
+    }
+}
+
+/// Whether another solid object is between the explosion `center` and the closest point of the `target`
+fn is_occluded(
+    rapier: &RapierContext,
+    center: Vec3,
+    hit: &BlastHit,
+    target: Entity,
+    filter: QueryFilter,
+    rigid_bodies: &Query<(), With<RigidBody>>,
+    parents: &Query<&Parent>,
+) -> bool {
+    let Some(direction) = (hit.point - center).try_normalize() else {
+        // Explosion inside the target
+        return false;
+    };
+    rapier
+        .cast_ray(center, direction, hit.distance, true, filter)
+        .is_some_and(|(collider, _)| hit_entity(collider, rigid_bodies, parents) != target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapon::tests::{run, weapon_app};
+
+    /// Static body with a hull, its surface is 1 meter closer to the explosion than its center
+    fn body(position: Vec3) -> impl Bundle {
+        (
+            Transform::from_translation(position),
+            RigidBody::Fixed,
+            Collider::ball(1.0),
+            Hull::new(1000.0),
+        )
+    }
+
+    /// Explodes at the world origin once the colliders are known to rapier
+    fn explode(app: &mut App, spec: ExplosionSpec) {
+        run(app, 0.1);
+        let world = app.world_mut();
+        let cause = world.spawn_empty().id();
+        world.send_event(Explosion {
+            center: Vec3::ZERO,
+            spec,
+            cause,
+        });
+        run(app, 0.1);
+    }
+
+    fn integrity(app: &App, entity: Entity) -> f32 {
+        app.world().get::<Hull>(entity).unwrap().integrity()
+    }
+
+    #[test]
+    fn damage_falls_off_with_distance() {
+        let mut app = weapon_app();
+        let world = app.world_mut();
+        let near = world.spawn(body(Vec3::new(6.0, 0.0, 0.0))).id();
+        let far = world.spawn(body(Vec3::new(0.0, 0.0, -16.0))).id();
+        let outside = world.spawn(body(Vec3::new(0.0, 25.0, 0.0))).id();
+
+        explode(&mut app, ExplosionSpec::new(20.0, 100.0, 0.0));
+
+        assert!((integrity(&app, near) - 925.0).abs() < 1e-3);
+        assert!((integrity(&app, far) - 975.0).abs() < 1e-3);
+        assert_eq!(integrity(&app, outside), 1000.0);
+    }
+
+    #[test]
+    fn occluded_bodies_are_protected() {
+        let mut app = weapon_app();
+        let world = app.world_mut();
+        let exposed = world.spawn(body(Vec3::new(-11.0, 0.0, 0.0))).id();
+        let hidden = world.spawn(body(Vec3::new(11.0, 0.0, 0.0))).id();
+        world.spawn((
+            Transform::from_xyz(5.0, 0.0, 0.0),
+            RigidBody::Fixed,
+            Collider::cuboid(0.5, 5.0, 5.0),
+        ));
+
+        let spec = ExplosionSpec::new(20.0, 100.0, 0.0)
+            .with_falloff(Falloff::Constant)
+            .with_occlusion();
+        explode(&mut app, spec);
+
+        assert_eq!(integrity(&app, exposed), 900.0);
+        assert_eq!(integrity(&app, hidden), 1000.0);
+    }
+}
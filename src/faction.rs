@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+/// Collision group of projectile colliders, so projectiles never hit each other
+pub(crate) const PROJECTILE_GROUP: Group = Group::GROUP_32;
+
+/// Side a ship fights for. Ship colliders are put into the collision group of their faction,
+/// so projectiles of the same faction skip them without any checks during hit processing.
+/// Ships and objects without a faction are neutral and can be hit by everyone.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Faction {
+    Alliance,
+    Raiders,
+}
+
+impl Faction {
+    /// Collision group of the faction ships
+    pub(crate) fn group(self) -> Group {
+        match self {
+            Faction::Alliance => Group::GROUP_1,
+            Faction::Raiders => Group::GROUP_2,
+        }
+    }
+
+    /// Components to assign the ship to the faction. Ships still collide with everything.
+    pub(crate) fn bundle(self) -> impl Bundle {
+        (self, CollisionGroups::new(self.group(), Group::ALL))
+    }
+}
+
+/// Whether projectiles hit ships of the shooter faction.
+/// The shooter itself is never hit by its own projectiles.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) enum FriendlyFire {
+    #[default]
+    Off,
+    On,
+}
@@ -0,0 +1,225 @@
+use bevy::prelude::*;
+
+use crate::weapon::{weapon_shooter, ShipMotion, Weapon, WeaponTypes};
+
+/// Weapon group bound to a fire action
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub(crate) enum WeaponGroup {
+    #[default]
+    Primary,
+    Secondary,
+}
+
+/// How weapons of the same group fire relative to each other
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) enum FireMode {
+    /// All weapons fire at the same time
+    #[default]
+    Linked,
+    /// Every weapon fires at its own rate, with shots evenly spread over the shot interval
+    Staggered,
+    /// Weapons take turns, so the group fires at the rate of a single weapon
+    Alternating,
+}
+
+impl FireMode {
+    fn next(self) -> Self {
+        match self {
+            FireMode::Linked => FireMode::Staggered,
+            FireMode::Staggered => FireMode::Alternating,
+            FireMode::Alternating => FireMode::Linked,
+        }
+    }
+}
+
+/// Delay of the first shot in shot intervals and the shot interval multiplier
+/// of the `index`-th of `count` weapons in a group firing in the `mode`
+pub(crate) fn firing_schedule(mode: FireMode, index: usize, count: usize) -> (f32, f32) {
+    match mode {
+        FireMode::Linked => (0.0, 1.0),
+        FireMode::Staggered => (index as f32 / count as f32, 1.0),
+        FireMode::Alternating => (index as f32, count as f32),
+    }
+}
+
+/// Fire control of a ship. Only weapons of the ship respond to its triggers.
+#[derive(Component, Clone, Debug)]
+pub(crate) struct FireControl {
+    /// Fire mode of each group, indexed by [`WeaponGroup`]
+    modes: [FireMode; 2],
+    /// Groups fired during this tick, should be set each tick by input system
+    triggers: [bool; 2],
+    /// Groups fired during the previous tick, to schedule shots once firing starts
+    was_firing: [bool; 2],
+}
+
+impl FireControl {
+    pub(crate) fn new(primary: FireMode, secondary: FireMode) -> Self {
+        Self {
+            modes: [primary, secondary],
+            triggers: [false; 2],
+            was_firing: [false; 2],
+        }
+    }
+
+    pub(crate) fn fire(&mut self, group: WeaponGroup) {
+        self.triggers[group as usize] = true;
+    }
+
+    /// Switches the group to the next fire mode and returns it
+    pub(crate) fn next_mode(&mut self, group: WeaponGroup) -> FireMode {
+        let mode = &mut self.modes[group as usize];
+        *mode = mode.next();
+        *mode
+    }
+}
+
+/// Fires weapons of the groups triggered via [`FireControl`], scheduling shots according to the fire modes
+pub(crate) fn fire_weapon_groups(
+    weapon_types: Res<WeaponTypes>,
+    mut ships: Query<(Entity, &mut FireControl)>,
+    mut weapons: Query<(Entity, &mut Weapon, &GlobalTransform)>,
+    motions: Query<ShipMotion>,
+    parents: Query<&Parent>,
+) {
+    // Weapons by ship, ordered by entity so the barrels take turns in a stable order
+    let mut ship_weapons: Vec<_> = weapons
+        .iter()
+        .filter_map(|(entity, weapon, transform)| {
+            let (ship, _) = weapon_shooter(entity, transform.translation(), &parents, &motions);
+            ship.map(|ship| (ship, weapon.group(), entity))
+        })
+        .collect();
+    ship_weapons.sort();
+
+    for (ship, mut control) in ships.iter_mut() {
+        for group in [WeaponGroup::Primary, WeaponGroup::Secondary] {
+            let slot = group as usize;
+            let is_firing = std::mem::take(&mut control.triggers[slot]);
+            let started = is_firing && !control.was_firing[slot];
+            control.was_firing[slot] = is_firing;
+            if !is_firing {
+                continue;
+            }
+
+            let group_weapons: Vec<_> = ship_weapons
+                .iter()
+                .filter(|(weapon_ship, weapon_group, _)| {
+                    *weapon_ship == ship && *weapon_group == group
+                })
+                .map(|(_, _, entity)| *entity)
+                .collect();
+            let count = group_weapons.len();
+            for (index, entity) in group_weapons.into_iter().enumerate() {
+                let (_, mut weapon, _) = weapons.get_mut(entity).unwrap();
+                let (delay, interval_scale) = firing_schedule(control.modes[slot], index, count);
+                weapon.interval_scale = interval_scale;
+                if started {
+                    let interval = weapon_types
+                        .get(&weapon)
+                        .and_then(|weapon_type| weapon_type.shot_interval())
+                        .unwrap_or_default();
+                    weapon.cooldown = weapon.cooldown.max(delay * interval);
+                }
+                weapon.fire();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::{
+        projectile::Bullet,
+        simulation::DEFAULT_TICK_RATE,
+        weapon::{
+            tests::{ship, weapon_app},
+            WeaponFireSet,
+        },
+    };
+
+    fn pull_triggers(mut controls: Query<&mut FireControl>) {
+        for mut control in controls.iter_mut() {
+            control.fire(WeaponGroup::Primary);
+        }
+    }
+
+    /// Ticks at which the left and the right barrel fired during a second of holding the trigger
+    fn firing_ticks(mode: FireMode) -> (Vec<usize>, Vec<usize>) {
+        let mut app = weapon_app();
+        app.add_systems(FixedUpdate, pull_triggers.before(WeaponFireSet));
+        app.world_mut()
+            .spawn((ship(Vec3::ZERO), FireControl::new(mode, FireMode::Linked)))
+            .with_children(|ship| {
+                // Left barrel is spawned first, so it's the first one to fire
+                for x in [-3.0, 3.0] {
+                    ship.spawn((
+                        Transform::from_xyz(x, 0.0, -3.0),
+                        Weapon::new("point_defense"),
+                    ));
+                }
+            });
+
+        // Bullets live longer than a second, so none of them is reused from the pool
+        let mut seen = BTreeSet::new();
+        let (mut left, mut right) = (Vec::new(), Vec::new());
+        for tick in 0..DEFAULT_TICK_RATE as usize {
+            app.update();
+            let world = app.world_mut();
+            for (entity, _, transform) in world
+                .query::<(Entity, &Bullet, &Transform)>()
+                .iter(world)
+                .filter(|(_, bullet, _)| bullet.is_live())
+            {
+                if seen.insert(entity) {
+                    if transform.translation.x < 0.0 {
+                        left.push(tick);
+                    } else {
+                        right.push(tick);
+                    }
+                }
+            }
+        }
+        (left, right)
+    }
+
+    #[test]
+    fn linked_barrels_fire_together() {
+        let (left, right) = firing_ticks(FireMode::Linked);
+        assert!(left.len() >= 14, "{left:?}");
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn staggered_barrels_fire_in_between() {
+        let (linked, _) = firing_ticks(FireMode::Linked);
+        let (left, right) = firing_ticks(FireMode::Staggered);
+        // Every barrel keeps its own rate, half an interval apart
+        assert_eq!(left, linked);
+        assert!(left.len().abs_diff(right.len()) <= 1, "{left:?} {right:?}");
+        assert!(left.iter().all(|tick| !right.contains(tick)));
+        assert!(right[0] > left[0] && right[0] < left[1]);
+    }
+
+    #[test]
+    fn alternating_barrels_take_turns() {
+        let (linked, _) = firing_ticks(FireMode::Linked);
+        let (left, right) = firing_ticks(FireMode::Alternating);
+        // The group fires at the rate of a single barrel
+        assert!((left.len() + right.len()).abs_diff(linked.len()) <= 1);
+        let mut shots: Vec<_> = left
+            .iter()
+            .map(|tick| (*tick, "left"))
+            .chain(right.iter().map(|tick| (*tick, "right")))
+            .collect();
+        shots.sort();
+        for (i, pair) in shots.windows(2).enumerate() {
+            assert!(pair[0].0 < pair[1].0, "{shots:?}");
+            let expected = if i % 2 == 0 { "left" } else { "right" };
+            assert_eq!(pair[0].1, expected, "{shots:?}");
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::GameStates;
+
+/// Point-mass gravity: every [`GravitySource`] pulls dynamic rigid bodies with `Velocity`
+/// by the inverse-square law. Global rapier gravity stays zero.
+pub(crate) struct GravityPlugin;
+impl Plugin for GravityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            apply_gravity
+                .before(PhysicsSet::SyncBackend)
+                .run_if(in_state(GameStates::Next)),
+        );
+    }
+}
+
+/// Celestial body attracting ships, e.g. a planet or a moon
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct GravitySource {
+    /// Standard gravitational parameter, the product of the gravitational constant and the mass
+    mu: f32,
+    /// Radius of the body. Gravity decreases linearly to zero towards the center inside of it,
+    /// as of a body with uniform density.
+    radius: f32,
+    /// Distance from the center outside of which the body doesn't attract anything
+    sphere_of_influence: Option<f32>,
+}
+
+impl GravitySource {
+    /// Body with the given gravity acceleration on its surface
+    pub(crate) fn from_surface_gravity(gravity: f32, radius: f32) -> Self {
+        Self {
+            mu: gravity * radius * radius,
+            radius,
+            sphere_of_influence: None,
+        }
+    }
+
+    pub(crate) fn with_sphere_of_influence(mut self, distance: f32) -> Self {
+        self.sphere_of_influence = Some(distance);
+        self
+    }
+
+    /// Standard gravitational parameter
+    pub(crate) fn mu(&self) -> f32 {
+        self.mu
+    }
+
+    /// Gravity acceleration of a body at the `offset` from the source center
+    pub(crate) fn acceleration(&self, offset: Vec3) -> Vec3 {
+        let distance = offset.length();
+        if distance == 0.0 || self.sphere_of_influence.is_some_and(|soi| distance > soi) {
+            return Vec3::ZERO;
+        }
+        let magnitude = if distance < self.radius {
+            self.mu * distance / self.radius.powi(3)
+        } else {
+            self.mu / (distance * distance)
+        };
+        -offset / distance * magnitude
+    }
+}
+
+/// Gravity changes velocity directly, as acceleration doesn't depend on the body mass.
+/// Applied before the physics step, which makes the integration semi-implicit and keeps orbits stable.
+fn apply_gravity(
+    time: Res<Time>,
+    sources: Query<(Entity, &GravitySource, &GlobalTransform)>,
+    mut bodies: Query<(Entity, &RigidBody, &GlobalTransform, &mut Velocity)>,
+) {
+    let dt = time.delta_secs();
+    for (entity, body, transform, mut velocity) in bodies.iter_mut() {
+        if *body != RigidBody::Dynamic {
+            continue;
+        }
+        let acceleration: Vec3 = sources
+            .iter()
+            // Celestial bodies don't pull themselves
+            .filter(|(source_entity, ..)| *source_entity != entity)
+            .map(|(_, source, source_transform)| {
+                source.acceleration(transform.translation() - source_transform.translation())
+            })
+            .sum();
+        if acceleration != Vec3::ZERO {
+            velocity.linvel += acceleration * dt;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::test_app;
+
+    #[test]
+    fn circular_orbit_is_stable() {
+        let mut app = test_app();
+        app.add_plugins(GravityPlugin);
+        let source = GravitySource::from_surface_gravity(100.0, 100.0);
+        app.world_mut().spawn((source, Transform::default()));
+
+        let radius = 200.0;
+        let speed = (source.mu() / radius).sqrt();
+        let period = std::f32::consts::TAU * radius / speed;
+        let ship = app
+            .world_mut()
+            .spawn((
+                Transform::from_xyz(radius, 0.0, 0.0),
+                RigidBody::Dynamic,
+                Collider::ball(1.0),
+                Velocity::linear(Vec3::new(0.0, 0.0, -speed)),
+            ))
+            .id();
+
+        // Three full orbits
+        let ticks = (3.0 * period * crate::simulation::DEFAULT_TICK_RATE as f32) as usize;
+        let (mut min, mut max) = (radius, radius);
+        for _ in 0..ticks {
+            app.update();
+            let distance = app
+                .world()
+                .get::<Transform>(ship)
+                .unwrap()
+                .translation
+                .length();
+            min = min.min(distance);
+            max = max.max(distance);
+        }
+        assert!(
+            min > radius * 0.99 && max < radius * 1.01,
+            "radius drifted to [{min}, {max}]"
+        );
+        // Still circling, not escaping or falling along a straight line
+        let velocity = app.world().get::<Velocity>(ship).unwrap().linvel;
+        assert!(
+            (velocity.length() - speed).abs() < speed * 0.01,
+            "{velocity}"
+        );
+    }
+}
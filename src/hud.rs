@@ -0,0 +1,427 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    camera::CameraRig,
+    controls::Action,
+    gravity::GravitySource,
+    hull::Hull,
+    orbit::OrbitalElements,
+    power::PowerPool,
+    simulation::TickInput,
+    targeting::{self, SelectedTarget},
+    weapon::{Weapon, WeaponTypes},
+    GameStates, Player, MOUSE_GUIDANCE_DEAD_ZONE,
+};
+
+/// Velocity markers are hidden below this speed, as their direction is meaningless
+const MIN_MARKER_SPEED: f32 = 0.1;
+/// Radius of prograde and retrograde markers in logical pixels
+const MARKER_RADIUS: f32 = 10.0;
+const PROGRADE_COLOR: egui::Color32 = egui::Color32::from_rgb(120, 230, 120);
+const RETROGRADE_COLOR: egui::Color32 = egui::Color32::from_rgb(230, 200, 90);
+const TARGET_COLOR: egui::Color32 = egui::Color32::from_rgb(240, 90, 80);
+const TEXT_COLOR: egui::Color32 = egui::Color32::from_rgb(200, 220, 240);
+
+/// Thrust actions with their HUD labels
+const THRUST_LABELS: [(Action, &str); 8] = [
+    (Action::Accelerate, "forward"),
+    (Action::Decelerate, "reverse"),
+    (Action::StrafeLeft, "left"),
+    (Action::StrafeRight, "right"),
+    (Action::StrafeUp, "up"),
+    (Action::StrafeDown, "down"),
+    (Action::RotateClockwise, "roll cw"),
+    (Action::RotateCounterClockwise, "roll ccw"),
+];
+
+/// Flight instruments drawn over the game view: speed, velocity markers, thrust and guidance state
+pub(crate) struct HudPlugin;
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, draw_hud.run_if(in_state(GameStates::Next)));
+    }
+}
+
+/// Projects world-space `direction` as seen by the perspective camera onto the screen.
+/// The direction is treated as a point at infinity, so the result doesn't depend on the camera position.
+///
+/// Returns position in logical pixels from the top-left corner of the `viewport`,
+/// or `None` if the direction points behind the camera. The position can be outside the viewport.
+pub(crate) fn project_direction(
+    camera_rotation: Quat,
+    fov_y: f32,
+    viewport: Vec2,
+    direction: Vec3,
+) -> Option<Vec2> {
+    // Camera looks along its local -Z
+    let local = camera_rotation.inverse() * direction;
+    if local.z >= 0.0 {
+        return None;
+    }
+    let half_height = (fov_y / 2.0).tan();
+    let half_width = half_height * viewport.x / viewport.y;
+    let ndc = Vec2::new(
+        local.x / (-local.z * half_width),
+        local.y / (-local.z * half_height),
+    );
+    Some(Vec2::new(
+        (ndc.x + 1.0) / 2.0 * viewport.x,
+        (1.0 - ndc.y) / 2.0 * viewport.y,
+    ))
+}
+
+/// Screen positions of the prograde and retrograde markers, `None` for hidden ones
+pub(crate) fn velocity_markers(
+    camera_rotation: Quat,
+    fov_y: f32,
+    viewport: Vec2,
+    velocity: Vec3,
+) -> (Option<Vec2>, Option<Vec2>) {
+    if velocity.length() < MIN_MARKER_SPEED {
+        return (None, None);
+    }
+    (
+        project_direction(camera_rotation, fov_y, viewport, velocity),
+        project_direction(camera_rotation, fov_y, viewport, -velocity),
+    )
+}
+
+/// Projects world-space `point` as seen by the perspective camera onto the screen,
+/// see [`project_direction`] for the returned value
+pub(crate) fn project_point(
+    camera: &Transform,
+    fov_y: f32,
+    viewport: Vec2,
+    point: Vec3,
+) -> Option<Vec2> {
+    project_direction(camera.rotation, fov_y, viewport, point - camera.translation)
+}
+
+fn pos(v: Vec2) -> egui::Pos2 {
+    egui::pos2(v.x, v.y)
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn draw_hud(
+    mut egui: EguiContexts,
+    input: Res<TickInput>,
+    target: Res<SelectedTarget>,
+    weapon_types: Option<Res<WeaponTypes>>,
+    player: Query<
+        (
+            Entity,
+            &Transform,
+            &Velocity,
+            Option<&Hull>,
+            Option<&PowerPool>,
+        ),
+        With<Player>,
+    >,
+    weapons: Query<(Entity, &Weapon)>,
+    parents: Query<&Parent>,
+    targets: Query<(&Transform, Option<&Velocity>, Option<&Name>)>,
+    gravity_sources: Query<(&GravitySource, &Transform, Option<&Velocity>, Option<&Name>)>,
+    cameras: Query<(&Camera, &Transform, &Projection), With<CameraRig>>,
+) {
+    let Ok((player_entity, player, velocity, hull, power)) = player.get_single() else {
+        return;
+    };
+    // Lead is shown for the first gun of the player, launchers and beams don't need it
+    let projectile_speed = weapon_types.as_ref().and_then(|weapon_types| {
+        weapons
+            .iter()
+            .filter(|(entity, _)| parents.iter_ancestors(*entity).any(|e| e == player_entity))
+            .find_map(|(_, weapon)| weapon_types.get(weapon)?.projectile_speed())
+    });
+    let velocity = velocity.linvel;
+    let target = target.0.and_then(|entity| targets.get(entity).ok());
+    let ctx = egui.ctx_mut();
+    // Below any windows, so HUD doesn't cover the inspector
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("hud"),
+    ));
+    let screen = ctx.screen_rect();
+
+    if let Ok((camera, transform, Projection::Perspective(projection))) = cameras.get_single() {
+        if let Some(viewport) = camera.logical_viewport_size() {
+            let (prograde, retrograde) =
+                velocity_markers(transform.rotation, projection.fov, viewport, velocity);
+            if let Some(prograde) = prograde {
+                // Circle with three ticks, like a wingless aircraft
+                let center = pos(prograde);
+                let stroke = egui::Stroke::new(2.0, PROGRADE_COLOR);
+                painter.circle_stroke(center, MARKER_RADIUS, stroke);
+                for tick in [
+                    egui::vec2(-1.0, 0.0),
+                    egui::vec2(1.0, 0.0),
+                    egui::vec2(0.0, -1.0),
+                ] {
+                    painter.line_segment(
+                        [
+                            center + tick * MARKER_RADIUS,
+                            center + tick * MARKER_RADIUS * 1.8,
+                        ],
+                        stroke,
+                    );
+                }
+            }
+            if let Some(retrograde) = retrograde {
+                // Circle with a cross
+                let center = pos(retrograde);
+                let stroke = egui::Stroke::new(2.0, RETROGRADE_COLOR);
+                let d = MARKER_RADIUS * std::f32::consts::FRAC_1_SQRT_2;
+                painter.circle_stroke(center, MARKER_RADIUS, stroke);
+                painter.line_segment(
+                    [center + egui::vec2(-d, -d), center + egui::vec2(d, d)],
+                    stroke,
+                );
+                painter.line_segment(
+                    [center + egui::vec2(-d, d), center + egui::vec2(d, -d)],
+                    stroke,
+                );
+            }
+
+            if let Some((target_transform, target_velocity, _)) = target {
+                let stroke = egui::Stroke::new(2.0, TARGET_COLOR);
+                let target_position = target_transform.translation;
+                if let Some(center) =
+                    project_point(transform, projection.fov, viewport, target_position)
+                {
+                    painter.rect_stroke(
+                        egui::Rect::from_center_size(pos(center), egui::Vec2::splat(30.0)),
+                        0.0,
+                        stroke,
+                    );
+                }
+                // Projectiles inherit the shooter velocity, so lead is computed from the relative motion
+                let lead = projectile_speed.and_then(|speed| {
+                    targeting::lead_position(
+                        player.translation,
+                        velocity,
+                        target_position,
+                        target_velocity.map(|v| v.linvel).unwrap_or_default(),
+                        speed,
+                    )
+                });
+                if let Some(lead) =
+                    lead.and_then(|lead| project_point(transform, projection.fov, viewport, lead))
+                {
+                    // Diamond where to aim to hit the target
+                    let center = pos(lead);
+                    let r = MARKER_RADIUS * 0.8;
+                    painter.add(egui::Shape::closed_line(
+                        vec![
+                            center + egui::vec2(0.0, -r),
+                            center + egui::vec2(r, 0.0),
+                            center + egui::vec2(0.0, r),
+                            center + egui::vec2(-r, 0.0),
+                        ],
+                        stroke,
+                    ));
+                }
+            }
+        }
+    }
+
+    let mouse_guidance = input.active(Action::MouseGuidance);
+    let click_guidance = input.active(Action::ClickGuidance);
+    if mouse_guidance || click_guidance {
+        // Cursor inside the dead zone doesn't rotate the ship in mouse guidance mode
+        let steering = click_guidance
+            || input
+                .cursor_offset
+                .is_some_and(|offset| offset.length() > MOUSE_GUIDANCE_DEAD_ZONE);
+        let color = if steering {
+            TEXT_COLOR
+        } else {
+            TEXT_COLOR.gamma_multiply(0.4)
+        };
+        painter.circle_stroke(
+            screen.center(),
+            MOUSE_GUIDANCE_DEAD_ZONE,
+            egui::Stroke::new(1.0, color),
+        );
+    }
+
+    let thrust: Vec<_> = THRUST_LABELS
+        .iter()
+        .filter(|(action, _)| input.active(*action))
+        .map(|(_, label)| *label)
+        .collect();
+    let mut lines = vec![
+        format!("Speed {:.1}", velocity.length()),
+        format!(
+            "Thrust {}",
+            if thrust.is_empty() {
+                "-".to_string()
+            } else {
+                thrust.join(" ")
+            }
+        ),
+        format!(
+            "Guidance {}",
+            match (mouse_guidance, click_guidance) {
+                (_, true) => "click",
+                (true, false) => "mouse",
+                (false, false) => "off",
+            }
+        ),
+    ];
+    if let Some(hull) = hull {
+        lines.push(format!(
+            "Hull {:.0}/{:.0}",
+            hull.integrity(),
+            hull.max_integrity()
+        ));
+    }
+    if let Some(power) = power {
+        lines.push(format!(
+            "Energy {:.0}/{:.0}",
+            power.energy(),
+            power.capacity()
+        ));
+    }
+    if let Some((target_transform, target_velocity, name)) = target {
+        let relative_position = target_transform.translation - player.translation;
+        let relative_velocity = target_velocity.map(|v| v.linvel).unwrap_or_default() - velocity;
+        lines.push(format!(
+            "Target {} {:.0} closing {:.1}",
+            name.map(Name::as_str).unwrap_or("?"),
+            relative_position.length(),
+            targeting::closing_speed(relative_position, relative_velocity),
+        ));
+    }
+    // Orbit around the body pulling the player the most
+    let primary = gravity_sources
+        .iter()
+        .map(|(source, transform, source_velocity, name)| {
+            let offset = player.translation - transform.translation;
+            let relative_velocity =
+                velocity - source_velocity.map(|v| v.linvel).unwrap_or_default();
+            (
+                source,
+                offset,
+                relative_velocity,
+                name,
+                source.acceleration(offset).length(),
+            )
+        })
+        .filter(|(.., acceleration)| *acceleration > 0.0)
+        .max_by(|a, b| a.4.total_cmp(&b.4));
+    if let Some((source, offset, relative_velocity, name, _)) = primary {
+        let mu = source.mu() as f64;
+        let name = name.map(Name::as_str).unwrap_or("?");
+        lines.push(
+            match OrbitalElements::from_state_vectors(
+                mu,
+                offset.as_dvec3(),
+                relative_velocity.as_dvec3(),
+            ) {
+                Some(orbit) => format!(
+                    "Orbit {name} Pe {:.0} Ap {:.0} T {:.0}s",
+                    orbit.periapsis(),
+                    orbit.apoapsis(),
+                    orbit.period(mu),
+                ),
+                None => format!("Orbit {name} escape"),
+            },
+        );
+    }
+    painter.text(
+        screen.center_bottom() - egui::vec2(0.0, 16.0),
+        egui::Align2::CENTER_BOTTOM,
+        lines.join("\n"),
+        egui::FontId::monospace(14.0),
+        TEXT_COLOR,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+
+    const VIEWPORT: Vec2 = Vec2::new(1600.0, 900.0);
+
+    #[test]
+    fn forward_direction_is_screen_center() {
+        let position = project_direction(Quat::IDENTITY, FRAC_PI_2, VIEWPORT, Vec3::NEG_Z).unwrap();
+        assert!(position.distance(VIEWPORT / 2.0) < 1e-3, "{position}");
+    }
+
+    #[test]
+    fn direction_behind_camera_is_hidden() {
+        assert_eq!(
+            project_direction(Quat::IDENTITY, FRAC_PI_2, VIEWPORT, Vec3::Z),
+            None
+        );
+        // Perpendicular to the view direction is on the camera plane, never on the screen
+        assert_eq!(
+            project_direction(Quat::IDENTITY, FRAC_PI_2, VIEWPORT, Vec3::X),
+            None
+        );
+        // Camera turned around sees what was behind it
+        let turned = Quat::from_rotation_y(PI);
+        assert!(project_direction(turned, FRAC_PI_2, VIEWPORT, Vec3::Z).is_some());
+    }
+
+    #[test]
+    fn direction_at_half_fov_is_on_screen_edge() {
+        // 90 degrees vertical field of view, so 45 degrees up is on the top edge
+        let up = project_direction(
+            Quat::IDENTITY,
+            FRAC_PI_2,
+            VIEWPORT,
+            Vec3::new(0.0, 1.0, -1.0),
+        )
+        .unwrap();
+        assert!(up.distance(Vec2::new(VIEWPORT.x / 2.0, 0.0)) < 1e-3, "{up}");
+
+        // Horizontal edge is wider by the aspect ratio
+        let aspect = VIEWPORT.x / VIEWPORT.y;
+        let right = project_direction(
+            Quat::IDENTITY,
+            FRAC_PI_2,
+            VIEWPORT,
+            Vec3::new(aspect, 0.0, -1.0),
+        )
+        .unwrap();
+        assert!(
+            right.distance(Vec2::new(VIEWPORT.x, VIEWPORT.y / 2.0)) < 1e-3,
+            "{right}"
+        );
+
+        // Beyond the edge is off screen, but still projected
+        let beyond = project_direction(
+            Quat::IDENTITY,
+            FRAC_PI_2,
+            VIEWPORT,
+            Vec3::new(0.0, -2.0, -1.0),
+        )
+        .unwrap();
+        assert!(beyond.y > VIEWPORT.y);
+    }
+
+    #[test]
+    fn velocity_markers_are_opposite() {
+        let (prograde, retrograde) = velocity_markers(
+            Quat::IDENTITY,
+            FRAC_PI_2,
+            VIEWPORT,
+            Vec3::new(0.0, 0.0, -50.0),
+        );
+        assert!(prograde.is_some_and(|position| position.distance(VIEWPORT / 2.0) < 1e-3));
+        // Retrograde marker is behind the camera
+        assert_eq!(retrograde, None);
+
+        let slow = Vec3::NEG_Z * MIN_MARKER_SPEED * 0.5;
+        assert_eq!(
+            velocity_markers(Quat::IDENTITY, FRAC_PI_2, VIEWPORT, slow),
+            (None, None)
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    explosion::{Explosion, Explosive},
+    GameStates,
+};
+
+/// Velocity change in a single impact below which the hull is not damaged, e.g. when docking gently
+const SAFE_IMPACT_VELOCITY: f32 = 5.0;
+/// Hull damage per unit of velocity change above [`SAFE_IMPACT_VELOCITY`]
+const DAMAGE_PER_IMPACT_VELOCITY: f32 = 4.0;
+
+/// Ships take damage from collisions, proportional to the velocity change of the impact.
+/// Destroyed ships are despawned after [`HullDestroyed`] is sent, [`Explosive`] ones explode.
+pub(crate) struct HullPlugin;
+impl Plugin for HullPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<HullHit>()
+            .add_event::<HullDestroyed>()
+            .add_systems(
+                FixedUpdate,
+                collision_damage
+                    .in_set(HullDamageSet)
+                    .after(PhysicsSet::Writeback)
+                    .run_if(in_state(GameStates::Next)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (log_hull_hits, destroy_ships)
+                    .chain()
+                    .after(HullDamageSet)
+                    .after(PhysicsSet::Writeback)
+                    .run_if(in_state(GameStates::Next)),
+            );
+    }
+}
+
+/// Systems that damage hulls and send [`HullDestroyed`]. Destroyed ships are despawned after all of them,
+/// so a kill is always handled during the same tick. The only exception is explosions, which are resolved
+/// after the destruction to include exploding ships, so their kills are handled during the next tick.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct HullDamageSet;
+
+/// Ship structural integrity. Requires contact force events on the ship collider
+/// and `ReadMassProperties` on the rigid body to take collision damage, see [`Hull::collision_bundle`].
+#[derive(Component, Clone, Debug)]
+pub(crate) struct Hull {
+    integrity: f32,
+    max_integrity: f32,
+}
+
+impl Hull {
+    pub(crate) fn new(max_integrity: f32) -> Self {
+        Self {
+            integrity: max_integrity,
+            max_integrity,
+        }
+    }
+
+    /// Components to report collisions of the entity with this hull
+    pub(crate) fn collision_bundle() -> impl Bundle {
+        (
+            ActiveEvents::CONTACT_FORCE_EVENTS,
+            ContactForceEventThreshold(0.0),
+            ReadMassProperties::default(),
+        )
+    }
+
+    pub(crate) fn integrity(&self) -> f32 {
+        self.integrity
+    }
+
+    pub(crate) fn max_integrity(&self) -> f32 {
+        self.max_integrity
+    }
+
+    pub(crate) fn is_destroyed(&self) -> bool {
+        self.integrity <= 0.0
+    }
+
+    /// Reduces integrity, returns `true` if this damage destroyed the hull
+    pub(crate) fn damage(&mut self, amount: f32) -> bool {
+        if self.is_destroyed() {
+            return false;
+        }
+        self.integrity = (self.integrity - amount).max(0.0);
+        self.is_destroyed()
+    }
+}
+
+/// Hull was damaged
+#[derive(Event, Clone, Debug)]
+pub(crate) struct HullHit {
+    pub(crate) entity: Entity,
+    /// Entity that caused the damage, e.g. another ship or a station
+    pub(crate) other: Entity,
+    pub(crate) damage: f32,
+}
+
+/// Hull integrity dropped to zero, the entity is despawned right after this event
+#[derive(Event, Clone, Debug)]
+pub(crate) struct HullDestroyed {
+    pub(crate) entity: Entity,
+}
+
+/// Collision damage of the body with `mass` that received the contact `impulse`.
+/// Velocity change of the impact accounts for both the relative velocity and masses of the bodies,
+/// so a light ship suffers more from hitting a heavy station than the other way around.
+pub(crate) fn impact_damage(impulse: f32, mass: f32) -> f32 {
+    if mass <= 0.0 {
+        return 0.0;
+    }
+    let velocity_change = impulse / mass;
+    (velocity_change - SAFE_IMPACT_VELOCITY).max(0.0) * DAMAGE_PER_IMPACT_VELOCITY
+}
+
+/// Finds the entity with the hull that the collider belongs to
+fn hull_entity(
+    collider: Entity,
+    hulls: &Query<(&mut Hull, &ReadMassProperties)>,
+    parents: &Query<&Parent>,
+) -> Option<Entity> {
+    std::iter::once(collider)
+        .chain(parents.iter_ancestors(collider))
+        .find(|entity| hulls.contains(*entity))
+}
+
+fn collision_damage(
+    time: Res<Time>,
+    mut contacts: EventReader<ContactForceEvent>,
+    mut hulls: Query<(&mut Hull, &ReadMassProperties)>,
+    parents: Query<&Parent>,
+    mut hits: EventWriter<HullHit>,
+    mut destroyed: EventWriter<HullDestroyed>,
+) {
+    for contact in contacts.read() {
+        // Forces are summed over the step, so the impulse is the force over the step duration
+        let impulse = contact.total_force_magnitude * time.delta_secs();
+        for (collider, other) in [
+            (contact.collider1, contact.collider2),
+            (contact.collider2, contact.collider1),
+        ] {
+            let Some(entity) = hull_entity(collider, &hulls, &parents) else {
+                continue;
+            };
+            let (mut hull, mass) = hulls.get_mut(entity).unwrap();
+            let damage = impact_damage(impulse, mass.mass);
+            if damage <= 0.0 || hull.is_destroyed() {
+                continue;
+            }
+            let was_destroyed = hull.damage(damage);
+            hits.send(HullHit {
+                entity,
+                other,
+                damage,
+            });
+            if was_destroyed {
+                destroyed.send(HullDestroyed { entity });
+            }
+        }
+    }
+}
+
+fn log_hull_hits(mut hits: EventReader<HullHit>) {
+    for hit in hits.read() {
+        debug!(
+            "{} took {} damage from {}",
+            hit.entity, hit.damage, hit.other
+        );
+    }
+}
+
+pub(crate) fn destroy_ships(
+    mut commands: Commands,
+    mut destroyed: EventReader<HullDestroyed>,
+    explosives: Query<(&Transform, &Explosive)>,
+    mut explosions: EventWriter<Explosion>,
+) {
+    for event in destroyed.read() {
+        info!("Destroyed {}", event.entity);
+        if let Ok((transform, explosive)) = explosives.get(event.entity) {
+            explosions.send(Explosion {
+                center: transform.translation,
+                spec: explosive.0,
+                cause: event.entity,
+            });
+        }
+        commands.entity(event.entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapon::tests::{run, weapon_app};
+
+    #[test]
+    fn impact_damage_grows_with_velocity_change() {
+        // 4 m/s velocity change is within the safe limit
+        assert_eq!(impact_damage(40.0, 10.0), 0.0);
+        assert_eq!(impact_damage(100.0, 10.0), 20.0);
+        // Heavier body changes velocity less from the same impulse
+        assert!(impact_damage(1000.0, 10.0) > impact_damage(1000.0, 50.0));
+        assert_eq!(impact_damage(1000.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn hull_is_destroyed_once() {
+        let mut hull = Hull::new(30.0);
+        assert!(!hull.damage(20.0));
+        assert!(hull.damage(20.0));
+        assert_eq!(hull.integrity(), 0.0);
+        assert!(!hull.damage(5.0));
+    }
+
+    fn colliding_ship(position: Vec3, speed: f32) -> impl Bundle {
+        (
+            Transform::from_translation(position),
+            RigidBody::Dynamic,
+            Collider::ball(1.0),
+            Velocity::linear(Vec3::new(0.0, 0.0, -speed)),
+            Hull::new(50.0),
+            Hull::collision_bundle(),
+        )
+    }
+
+    #[test]
+    fn ship_crashing_into_wall_is_destroyed() {
+        let mut app = weapon_app();
+        let world = app.world_mut();
+        world.spawn((
+            Transform::from_xyz(0.0, 0.0, -20.0),
+            RigidBody::Fixed,
+            Collider::cuboid(50.0, 10.0, 1.0),
+        ));
+        let crashing = world.spawn(colliding_ship(Vec3::ZERO, 60.0)).id();
+        // Touches the wall gently later
+        let docking = world
+            .spawn(colliding_ship(Vec3::new(20.0, 0.0, -17.0), 3.0))
+            .id();
+
+        run(&mut app, 1.5);
+
+        let world = app.world();
+        assert!(world.get_entity(crashing).is_err());
+        let docking_position = world.get::<Transform>(docking).unwrap().translation;
+        // Resting against the wall, not passing through it
+        assert!(
+            (-18.1..-17.9).contains(&docking_position.z),
+            "{docking_position}"
+        );
+        assert_eq!(world.get::<Hull>(docking).unwrap().integrity(), 50.0);
+    }
+}
@@ -1,10 +1,37 @@
-use std::f32::consts::PI;
+use std::{f32::consts::PI, path::PathBuf, time::Duration};
 
-use bevy::{core_pipeline::Skybox, prelude::*};
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    core_pipeline::Skybox,
+    math::DVec3,
+    prelude::*,
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier3d::prelude::*;
 
 mod assets;
+mod beam;
+mod benchmark;
+mod camera;
+mod controls;
+mod explosion;
+mod faction;
+mod fire_control;
+mod gravity;
+mod hud;
+mod hull;
+mod missile;
+mod orbit;
+mod origin;
+mod power;
+mod projectile;
+mod replay;
+mod simulation;
+mod targeting;
+mod turret;
 mod weapon;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Default, States)]
@@ -14,28 +41,129 @@ enum GameStates {
     Next,
 }
 
+/// Radius in logical pixels around the screen center where cursor doesn't rotate the ship in mouse guidance mode
+const MOUSE_GUIDANCE_DEAD_ZONE: f32 = 20.0;
+
+/// Explosion of a destroyed fighter
+const SHIP_EXPLOSION: explosion::ExplosionSpec = explosion::ExplosionSpec::new(30.0, 60.0, 15000.0);
+
+/// Command line arguments
+#[derive(Default)]
+struct Args {
+    /// `--record <path>`: write all player inputs to the file, see [`replay::RecordPlugin`]
+    record: Option<PathBuf>,
+    /// `--replay <path>`: replay recorded session without window, see [`replay::ReplayPlugin`]
+    replay: Option<PathBuf>,
+    /// `--benchmark <projectiles>`: keep the number of projectiles flying without window and print
+    /// simulation tick times, see [`benchmark::BenchmarkPlugin`]
+    benchmark: Option<usize>,
+    /// `--tick-rate <hz>`: simulation ticks per second. Ignored for replay, as the recorded rate is used.
+    tick_rate: Option<u32>,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut parsed = Self::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            // Logger is not initialized yet, so errors are printed directly
+            match (arg.as_str(), args.next()) {
+                ("--record", Some(path)) => parsed.record = Some(path.into()),
+                ("--replay", Some(path)) => parsed.replay = Some(path.into()),
+                ("--benchmark", Some(count)) => match count.parse() {
+                    Ok(count) => parsed.benchmark = Some(count),
+                    _ => eprintln!("Invalid projectile count {count}"),
+                },
+                ("--tick-rate", Some(rate)) => match rate.parse() {
+                    Ok(rate) if rate > 0 => parsed.tick_rate = Some(rate),
+                    _ => eprintln!("Invalid tick rate {rate}"),
+                },
+                _ => eprintln!("Unknown argument {arg}"),
+            }
+        }
+        parsed
+    }
+}
+
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_plugins(WorldInspectorPlugin::new())
-        .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
-        .add_plugins(RapierDebugRenderPlugin::default())
+    let args = Args::parse();
+    let mut tick_rate = args.tick_rate.unwrap_or(simulation::DEFAULT_TICK_RATE);
+
+    let mut app = App::new();
+    if let Some(path) = args.replay {
+        let replay = replay::ReplayPlugin::load(&path);
+        tick_rate = replay.tick_rate();
+        add_headless_plugins(&mut app);
+        app.add_plugins(replay);
+    } else if let Some(projectiles) = args.benchmark {
+        add_headless_plugins(&mut app);
+        app.add_plugins(benchmark::BenchmarkPlugin {
+            projectiles,
+            tick_rate,
+        });
+    } else {
+        app.add_plugins(DefaultPlugins)
+            .add_plugins(WorldInspectorPlugin::new())
+            .add_plugins(RapierDebugRenderPlugin::default())
+            .add_plugins(controls::ControlsPlugin)
+            .add_plugins(targeting::TargetingPlugin)
+            .add_plugins(camera::CameraPlugin)
+            .add_plugins(hud::HudPlugin);
+        if let Some(path) = args.record {
+            app.add_plugins(replay::RecordPlugin(path));
+        }
+    }
+
+    app.add_plugins(simulation::SimulationPlugin { tick_rate })
         .add_plugins(assets::AssetsPlugin)
         .add_plugins(weapon::WeaponPlugin)
+        .add_plugins(origin::OriginPlugin)
+        .add_plugins(gravity::GravityPlugin)
+        .add_plugins(orbit::OrbitPlugin)
+        .add_plugins(hull::HullPlugin)
+        .add_plugins(explosion::ExplosionPlugin)
+        .add_plugins(power::PowerPlugin)
+        .add_plugins(turret::TurretPlugin)
         .init_state::<GameStates>()
-        .init_resource::<ControlsConfig>()
         .add_systems(
             OnEnter(GameStates::Next),
             (setup_light, setup_rapier, setup),
         )
         .add_systems(
-            Update,
-            (player_controller, weapon_fire, animate_light_direction)
+            FixedUpdate,
+            (player_controller, weapon_fire.before(weapon::WeaponFireSet))
+                .before(PhysicsSet::SyncBackend)
                 .run_if(in_state(GameStates::Next)),
         )
+        .add_systems(
+            Update,
+            animate_light_direction.run_if(in_state(GameStates::Next)),
+        )
         .run();
 }
 
+/// Headless app without window and GPU, updated as fast as possible
+fn add_headless_plugins(app: &mut App) {
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                ..default()
+            })
+            .set(RenderPlugin {
+                render_creation: WgpuSettings {
+                    backends: None,
+                    ..default()
+                }
+                .into(),
+                ..default()
+            })
+            .disable::<WinitPlugin>(),
+    )
+    .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::ZERO));
+}
+
 // todo: replace by EnvironmentMapLight
 fn setup_light(mut commands: Commands) {
     // directional 'sun' light
@@ -66,49 +194,71 @@ fn setup(
     mut commands: Commands,
     models: Res<assets::Models>,
     environment: Res<assets::Environment>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
+    // The moon is spawned below, the station orbit only needs its entity and gravity
+    let moon = commands.spawn_empty().id();
+    let moon_radius = 150.0;
+    let moon_position = Vec3::new(600.0, 0.0, -700.0);
+    let moon_gravity = gravity::GravitySource::from_surface_gravity(2.0, moon_radius)
+        .with_sphere_of_influence(500.0);
+
+    // Station is on a circular orbit around the moon, within its sphere of influence
+    let station_position = moon_position + Vec3::new(-350.0, 0.0, 0.0);
+    let offset = (station_position - moon_position).as_dvec3();
+    let speed = (moon_gravity.mu() as f64 / offset.length()).sqrt();
+    let station_orbit = orbit::Orbit {
+        primary: moon,
+        elements: orbit::OrbitalElements::from_state_vectors(
+            moon_gravity.mu() as f64,
+            offset,
+            DVec3::Y.cross(offset).normalize() * speed,
+        )
+        .unwrap(),
+        epoch: 0.0,
+    };
+
     commands
         .spawn(SceneRoot(models.zenith_station.clone()))
         .insert(Transform {
-            translation: -200.0 * Vec3::Z,
+            translation: station_position,
             ..default()
         })
+        .insert(origin::UniversePosition(station_position.as_dvec3()))
+        .insert(RigidBody::KinematicPositionBased)
+        .insert(station_orbit)
+        .insert(simulation::VisualInterpolation::default())
         .insert(Name::new("Zenith station"));
 
-    commands
+    let praetor = commands
         .spawn(SceneRoot(models.praetor.clone()))
         .insert(Transform {
             translation: Vec3::new(5.0, 5.0, -20.0),
             ..default()
         })
+        .insert(origin::UniversePosition(DVec3::new(5.0, 5.0, -20.0)))
         .insert(Player)
+        .insert(faction::Faction::Alliance.bundle())
         .insert(RigidBody::Dynamic)
+        .insert(explosion::Explosive(SHIP_EXPLOSION))
+        .insert(simulation::VisualInterpolation::default())
         .insert(Restitution::coefficient(0.7))
+        .insert(hull::Hull::new(100.0))
+        .insert(hull::Hull::collision_bundle())
+        .insert(power::PowerPool::new(100.0, 25.0))
+        .insert(fire_control::FireControl::new(
+            fire_control::FireMode::Staggered,
+            fire_control::FireMode::Linked,
+        ))
         .insert(Damping {
             linear_damping: 0.0,
             angular_damping: 1.0,
         })
         .insert(ExternalForce::default())
+        // Weapon recoil and projectile hits
+        .insert(ExternalImpulse::default())
         .insert(Velocity::default())
-        .with_children(|parent| {
-            parent.spawn((
-                Camera3d::default(),
-                // slightly behind and above the spaceship
-                Transform::from_xyz(0.0, 3.0, 20.0),
-                Skybox {
-                    image: environment.skybox_image.clone(),
-                    brightness: 1500.0,
-                    ..default()
-                },
-                // todo: specify environment light according to the skybox
-                // see the scene_viewer example for more details:
-                // EnvironmentMapLight {
-                //     diffuse_map: asset_server.load("assets/environment_maps/pisa_diffuse_rgb9e5_zstd.ktx2"),
-                //     specular_map: asset_server
-                //         .load("assets/environment_maps/pisa_specular_rgb9e5_zstd.ktx2"),
-                // },
-            ));
-        })
         .insert(assets::SceneSetup::new(|commands, entities| {
             entities
                 .iter()
@@ -116,20 +266,63 @@ fn setup(
                 .filter_map(|e| e.get::<Name>().map(|name| (e.id(), name)))
                 .for_each(|(entity, name)| {
                     if name.starts_with("barrel.") {
-                        commands.entity(entity).insert(weapon::Weapon::new(7.0));
+                        // Wing barrels cross at the target in front of the nose
+                        commands.entity(entity).insert(
+                            weapon::Weapon::new("autocannon").with_convergence(
+                                weapon::Convergence::at(300.0).with_target(0.15, 800.0),
+                            ),
+                        );
+                    } else if name.starts_with("camera.") {
+                        commands.entity(entity).insert(camera::CockpitAnchor);
                     }
                 });
         }))
-        .insert(Name::new("Praetor"));
+        .insert(Name::new("Praetor"))
+        .id();
+    // Missile launcher under the hull, fired by the secondary group
+    commands.entity(praetor).with_child((
+        Transform::from_xyz(0.0, -1.0, -2.0),
+        weapon::Weapon::new("missile").with_group(fire_control::WeaponGroup::Secondary),
+        Name::new("Launcher"),
+    ));
 
-    commands
+    commands.spawn((
+        Camera3d::default(),
+        // Starts slightly behind and above the spaceship, then controlled by the rig
+        Transform::from_xyz(5.0, 8.0, 0.0),
+        camera::CameraRig::follow(praetor),
+        Skybox {
+            image: environment.skybox_image.clone(),
+            brightness: 1500.0,
+            ..default()
+        },
+        // todo: specify environment light according to the skybox
+        // see the scene_viewer example for more details:
+        // EnvironmentMapLight {
+        //     diffuse_map: asset_server.load("assets/environment_maps/pisa_diffuse_rgb9e5_zstd.ktx2"),
+        //     specular_map: asset_server
+        //         .load("assets/environment_maps/pisa_specular_rgb9e5_zstd.ktx2"),
+        // },
+        Name::new("Camera"),
+    ));
+
+    let infiltrator = commands
         .spawn(SceneRoot(models.infiltrator.clone()))
         .insert(Transform {
             translation: Vec3::new(-5.0, 5.0, -20.0),
             ..default()
         })
+        .insert(origin::UniversePosition(DVec3::new(-5.0, 5.0, -20.0)))
         .insert(RigidBody::Dynamic)
+        .insert(simulation::VisualInterpolation::default())
         .insert(Restitution::coefficient(0.7))
+        .insert(hull::Hull::new(100.0))
+        .insert(hull::Hull::collision_bundle())
+        .insert(Velocity::default())
+        .insert(ExternalImpulse::default())
+        .insert(targeting::Hostile)
+        .insert(faction::Faction::Raiders.bundle())
+        .insert(explosion::Explosive(SHIP_EXPLOSION))
         .insert(assets::SceneSetup::new(|commands, entities| {
             entities
                 .iter()
@@ -137,19 +330,103 @@ fn setup(
                 .filter_map(|e| e.get::<Name>().map(|name| (e.id(), name)))
                 .for_each(|(entity, name)| {
                     if name.starts_with("barrel.") {
-                        commands.entity(entity).insert(weapon::Weapon::new(3.5));
+                        commands.entity(entity).insert(weapon::Weapon::new("laser"));
+                    } else if name.starts_with("camera.") {
+                        commands.entity(entity).insert(camera::CockpitAnchor);
                     }
                 });
         }))
-        .insert(Name::new("Infiltrator"));
+        .insert(Name::new("Infiltrator"))
+        .id();
+    // Blaster under the nose, fired along with the lasers, and a missile launcher on top
+    commands.entity(infiltrator).with_children(|ship| {
+        ship.spawn((
+            Transform::from_xyz(0.0, -0.8, -2.5),
+            weapon::Weapon::new("blaster"),
+            Name::new("Blaster"),
+        ));
+        ship.spawn((
+            Transform::from_xyz(0.0, 1.0, -1.5),
+            weapon::Weapon::new("missile").with_group(fire_control::WeaponGroup::Secondary),
+            Name::new("Launcher"),
+        ));
+    });
 
-    commands
+    let dragoon = commands
         .spawn(SceneRoot(models.dragoon.clone()))
         .insert(Transform {
             translation: Vec3::new(0.0, 5.0, 150.0),
             ..default()
         })
-        .insert(Name::new("Dragoon"));
+        .insert(origin::UniversePosition(DVec3::new(0.0, 5.0, 150.0)))
+        .insert(RigidBody::Dynamic)
+        .insert(simulation::VisualInterpolation::default())
+        .insert(hull::Hull::new(300.0))
+        .insert(hull::Hull::collision_bundle())
+        .insert(Velocity::default())
+        .insert(ExternalImpulse::default())
+        .insert(faction::Faction::Alliance.bundle())
+        // Reactor of a capital ship takes everything around with it
+        .insert(explosion::Explosive(
+            explosion::ExplosionSpec::new(80.0, 150.0, 60000.0)
+                .with_falloff(explosion::Falloff::Quadratic)
+                .with_occlusion(),
+        ))
+        .insert(assets::SceneSetup::new(|commands, entities| {
+            entities
+                .iter()
+                .filter(|e| !e.contains::<Mesh3d>()) // Skip GLTF Mesh entities
+                .filter_map(|e| e.get::<Name>().map(|name| (e.id(), name)))
+                .for_each(|(entity, name)| {
+                    if name.starts_with("turret_base.") {
+                        // Can't fire right behind, at the bridge
+                        commands.entity(entity).insert(
+                            turret::Turret::new(1.5, 600.0)
+                                .with_yaw_arc(-0.8 * PI, 0.8 * PI)
+                                .with_pitch_arc(-0.1, 1.4),
+                        );
+                    } else if name.starts_with("turret_gun.") {
+                        commands.entity(entity).insert((
+                            turret::TurretGun::default(),
+                            weapon::Weapon::new("autocannon"),
+                        ));
+                    }
+                });
+        }))
+        .insert(Name::new("Dragoon"))
+        .id();
+    // Point defense mounts on the top and the bottom of the hull, covering a hemisphere each
+    commands.entity(dragoon).with_children(|ship| {
+        for (height, roll) in [(8.0, 0.0), (-8.0, PI)] {
+            ship.spawn((
+                Transform::from_xyz(0.0, height, 0.0).with_rotation(Quat::from_rotation_z(roll)),
+                turret::Turret::new(4.0, 400.0).with_point_defense(40.0),
+                Name::new("Point defense"),
+            ))
+            .with_child((
+                Transform::default(),
+                turret::TurretGun::default(),
+                weapon::Weapon::new("point_defense"),
+                Name::new("Point defense gun"),
+            ));
+        }
+    });
+
+    // Small moon, far enough to not pull ships near the spawn point
+    commands.entity(moon).insert((
+        Mesh3d(meshes.add(Sphere::new(moon_radius).mesh().ico(5).unwrap())),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgb_u8(140, 135, 130),
+            perceptual_roughness: 1.0,
+            ..default()
+        })),
+        Transform::from_translation(moon_position),
+        origin::UniversePosition(moon_position.as_dvec3()),
+        RigidBody::Fixed,
+        Collider::ball(moon_radius),
+        moon_gravity,
+        Name::new("Moon"),
+    ));
 }
 
 fn animate_light_direction(
@@ -161,93 +438,49 @@ fn animate_light_direction(
     }
 }
 
-#[derive(Resource)]
-struct ControlsConfig {
-    key_accelerate: KeyCode,
-    key_decelerate: KeyCode,
-    key_strafe_left: KeyCode,
-    key_strafe_right: KeyCode,
-    key_strafe_up: KeyCode,
-    key_strage_down: KeyCode,
-    key_rotate_clockwise: KeyCode,
-    key_rotate_counter_clockwise: KeyCode,
-
-    key_primary_weapon_fire: KeyCode,
-}
-
-impl Default for ControlsConfig {
-    fn default() -> Self {
-        Self {
-            key_accelerate: KeyCode::KeyX,
-            key_decelerate: KeyCode::KeyZ,
-            key_strafe_left: KeyCode::KeyA,
-            key_strafe_right: KeyCode::KeyD,
-            key_strafe_up: KeyCode::KeyW,
-            key_strage_down: KeyCode::KeyS,
-            key_rotate_clockwise: KeyCode::KeyE,
-            key_rotate_counter_clockwise: KeyCode::KeyQ,
-
-            key_primary_weapon_fire: KeyCode::Space,
-        }
-    }
-}
-
 fn player_controller(
-    config: Res<ControlsConfig>,
-    keys: Res<ButtonInput<KeyCode>>,
-    mouse: Res<ButtonInput<MouseButton>>,
-    mut mouse_guidance: Local<bool>,
-    mut windows: Query<&mut Window>,
-    mut egui: bevy_inspector_egui::bevy_egui::EguiContexts,
+    input: Res<simulation::TickInput>,
     mut player: Query<(&Transform, &mut ExternalForce), With<Player>>,
 ) {
-    let (transform, mut force) = player.single_mut();
+    use controls::Action;
+
+    // Player may be destroyed
+    let Ok((transform, mut force)) = player.get_single_mut() else {
+        return;
+    };
 
     force.force = Vec3::ZERO;
-    if keys.pressed(config.key_strafe_up) {
+    if input.active(Action::StrafeUp) {
         force.force += transform.up() * 100.0;
     }
-    if keys.pressed(config.key_strage_down) {
+    if input.active(Action::StrafeDown) {
         force.force += transform.down() * 100.0;
     }
-    if keys.pressed(config.key_strafe_left) {
+    if input.active(Action::StrafeLeft) {
         force.force += transform.left() * 100.0;
     }
-    if keys.pressed(config.key_strafe_right) {
+    if input.active(Action::StrafeRight) {
         force.force += transform.right() * 100.0;
     }
-    if keys.pressed(config.key_accelerate) {
+    if input.active(Action::Accelerate) {
         force.force += transform.forward() * 1000.0;
     }
-    if keys.pressed(config.key_decelerate) {
+    if input.active(Action::Decelerate) {
         force.force += transform.back() * 1000.0;
     }
 
     force.torque = Vec3::ZERO;
-    if keys.pressed(config.key_rotate_counter_clockwise) {
+    if input.active(Action::RotateCounterClockwise) {
         force.torque += transform.back() * 300.0;
     }
-    if keys.pressed(config.key_rotate_clockwise) {
+    if input.active(Action::RotateClockwise) {
         force.torque += transform.forward() * 300.0;
     }
 
-    // Enable mouse guidance if Space is pressed
-    if keys.just_released(KeyCode::Space) {
-        *mouse_guidance = !*mouse_guidance;
-    }
-
-    let click_guidance = !egui.ctx_mut().is_pointer_over_area()
-        && !egui.ctx_mut().is_using_pointer()
-        && mouse.pressed(MouseButton::Left);
-    if *mouse_guidance || click_guidance {
-        let window = windows.single_mut();
-
-        if let Some(pos) = window.cursor_position() {
-            let center = Vec2::new(window.width() / 2.0, window.height() / 2.0);
-            let offset = center - pos;
-
-            // Safe zone around screen center for mouse_guidance mode
-            if click_guidance || offset.length_squared() > 400.0 {
+    let click_guidance = input.active(Action::ClickGuidance);
+    if input.active(Action::MouseGuidance) || click_guidance {
+        if let Some(offset) = input.cursor_offset {
+            if click_guidance || offset.length() > MOUSE_GUIDANCE_DEAD_ZONE {
                 force.torque += transform.up() * offset.x;
                 force.torque += transform.right() * offset.y;
             }
@@ -256,13 +489,24 @@ fn player_controller(
 }
 
 fn weapon_fire(
-    config: Res<ControlsConfig>,
-    keys: Res<ButtonInput<KeyCode>>,
-    mut weapon: Query<&mut weapon::Weapon /*, With<Player>*/>,
+    input: Res<simulation::TickInput>,
+    // Tick input only has held actions, so switching is done once the action is activated
+    mut was_switching_mode: Local<bool>,
+    mut fire_control: Query<&mut fire_control::FireControl, With<Player>>,
 ) {
-    if keys.pressed(config.key_primary_weapon_fire) {
-        for mut weapon in &mut weapon {
-            weapon.fire();
-        }
+    let Ok(mut fire_control) = fire_control.get_single_mut() else {
+        return;
+    };
+    let switch_mode = input.active(controls::Action::NextFireMode);
+    if switch_mode && !*was_switching_mode {
+        let mode = fire_control.next_mode(fire_control::WeaponGroup::Primary);
+        info!("Primary fire mode: {mode:?}");
+    }
+    *was_switching_mode = switch_mode;
+    if input.active(controls::Action::PrimaryFire) {
+        fire_control.fire(fire_control::WeaponGroup::Primary);
+    }
+    if input.active(controls::Action::SecondaryFire) {
+        fire_control.fire(fire_control::WeaponGroup::Secondary);
     }
 }
@@ -0,0 +1,621 @@
+use bevy::{
+    ecs::event::Events,
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+};
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    explosion::{ExplosionSpec, Explosive},
+    faction::{Faction, FriendlyFire},
+    hull::Hull,
+    projectile::HitVolume,
+    simulation::VisualInterpolation,
+    weapon::{
+        weapon_shooter, Damage, Lifetime, Projectile, ProjectileHit, ProjectileOwner, ShipMotion,
+        Weapon, WeaponType, WeaponTypes,
+    },
+};
+
+/// Catalog entry of a missile launcher
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct LauncherSpec {
+    /// Seconds between launches
+    reload: f32,
+    /// Seconds the seeker should track the same target before a missile can be launched
+    lock_time: f32,
+    /// Half-angle of the seeker cone in degrees
+    lock_angle: f32,
+    /// Maximum distance to a target the seeker can lock on
+    lock_range: f32,
+    missile: MissileSpec,
+}
+
+/// Catalog entry of a guided missile
+#[derive(Deserialize, Clone, Debug)]
+struct MissileSpec {
+    /// Missile is a capsule aligned with its nose
+    radius: f32,
+    /// Full length of the capsule, should be at least twice the radius
+    length: f32,
+    /// sRGB color of the unlit missile material
+    color: [f32; 3],
+    mass: f32,
+    /// Speed relative to the launcher
+    launch_speed: f32,
+    /// Acceleration of the engine
+    thrust: f32,
+    /// Seconds the engine can burn, the missile keeps flying ballistically afterwards
+    fuel: f32,
+    /// Maximum turn rate in degrees per second
+    turn_rate: f32,
+    /// Proportional navigation constant, usually between 3 and 5
+    #[serde(default = "default_navigation_constant")]
+    navigation_constant: f32,
+    /// Distance to the target at which the warhead explodes
+    proximity: f32,
+    /// Seconds before the missile self-destructs, its warhead explodes if it has one
+    lifetime: f32,
+    /// Damage of a direct hit, dealt to the target in addition to the explosion
+    damage: f32,
+    /// Warhead exploding on hit, both direct and proximity ones
+    #[serde(default)]
+    explosion: Option<ExplosionSpec>,
+}
+
+fn default_navigation_constant() -> f32 {
+    4.0
+}
+
+impl LauncherSpec {
+    /// Rejects values the launcher can't fire with, like a zero reload that launches every tick
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.reload <= 0.0 {
+            return Err(format!(
+                "launcher reload {} should be positive",
+                self.reload
+            ));
+        }
+        if self.lock_time < 0.0 {
+            return Err(format!(
+                "launcher lock_time {} should not be negative",
+                self.lock_time
+            ));
+        }
+        if self.lock_angle <= 0.0 || self.lock_angle > 180.0 {
+            return Err(format!(
+                "launcher lock_angle {} should be within (0, 180] degrees",
+                self.lock_angle
+            ));
+        }
+        if self.lock_range <= 0.0 {
+            return Err(format!(
+                "launcher lock_range {} should be positive",
+                self.lock_range
+            ));
+        }
+        self.missile.validate()
+    }
+}
+
+impl MissileSpec {
+    fn validate(&self) -> Result<(), String> {
+        if self.radius <= 0.0 {
+            return Err(format!("missile radius {} should be positive", self.radius));
+        }
+        if self.length < 2.0 * self.radius {
+            return Err(format!(
+                "missile length {} should be at least twice the radius {}",
+                self.length, self.radius
+            ));
+        }
+        // Missiles are dynamic bodies, rapier can't move them without mass
+        if self.mass <= 0.0 {
+            return Err(format!("missile mass {} should be positive", self.mass));
+        }
+        if self.lifetime <= 0.0 {
+            return Err(format!(
+                "missile lifetime {} should be positive",
+                self.lifetime
+            ));
+        }
+        for (field, value) in [
+            ("thrust", self.thrust),
+            ("fuel", self.fuel),
+            ("turn_rate", self.turn_rate),
+            ("proximity", self.proximity),
+        ] {
+            if value < 0.0 {
+                return Err(format!("missile {field} {value} should not be negative"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Launcher catalog entry prepared for spawning missiles
+pub(crate) struct LauncherType {
+    reload: f32,
+    lock_time: f32,
+    /// Seeker cone half-angle in radians
+    lock_angle: f32,
+    lock_range: f32,
+
+    collider: Collider,
+    /// Sphere enclosing the missile, so point defense can shoot it down
+    hit_volume: HitVolume,
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+    mass: f32,
+    launch_speed: f32,
+    guidance: Missile,
+    lifetime: f32,
+    damage: f32,
+    explosion: Option<ExplosionSpec>,
+}
+
+impl LauncherType {
+    /// Seconds between launches
+    pub(crate) fn reload(&self) -> f32 {
+        self.reload
+    }
+
+    pub(crate) fn new(
+        spec: &LauncherSpec,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Self {
+        let missile = &spec.missile;
+        let half_length = (missile.length / 2.0 - missile.radius).max(0.0);
+        let [red, green, blue] = missile.color;
+        Self {
+            reload: spec.reload,
+            lock_time: spec.lock_time,
+            lock_angle: spec.lock_angle.to_radians(),
+            lock_range: spec.lock_range,
+            collider: Collider::capsule_y(half_length, missile.radius),
+            hit_volume: HitVolume {
+                radius: missile.length / 2.0,
+            },
+            mesh: meshes.add(Mesh::from(Capsule3d {
+                radius: missile.radius,
+                half_length,
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::srgb(red, green, blue),
+                unlit: true,
+                ..default()
+            }),
+            mass: missile.mass,
+            launch_speed: missile.launch_speed,
+            guidance: Missile {
+                target: None,
+                thrust: missile.thrust,
+                fuel: missile.fuel,
+                turn_rate: missile.turn_rate.to_radians(),
+                navigation_constant: missile.navigation_constant,
+                proximity: missile.proximity,
+            },
+            lifetime: missile.lifetime,
+            damage: missile.damage,
+            explosion: missile.explosion,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn spawn(
+        &self,
+        commands: &mut Commands,
+        position: Vec3,
+        direction: Vec3,
+        velocity: Vec3,
+        owner: ProjectileOwner,
+        friendly_fire: FriendlyFire,
+        target: Entity,
+    ) {
+        let mut missile = commands.spawn((
+            Mesh3d(self.mesh.clone()),
+            MeshMaterial3d(self.material.clone()),
+            Transform {
+                translation: position,
+                // Missile nose is along Vec3::Y as of the capsule
+                rotation: Quat::from_rotation_arc(Vec3::Y, direction),
+                scale: Vec3::ONE,
+            },
+            Lifetime(self.lifetime),
+            // Dynamic, so the engine and gravity accelerate it
+            RigidBody::Dynamic,
+            (
+                AdditionalMassProperties::Mass(self.mass),
+                // Hits push targets with the missile momentum
+                ReadMassProperties::default(),
+            ),
+            Velocity {
+                linvel: velocity,
+                ..default()
+            },
+            (
+                self.collider.clone(),
+                // Direct hits are detected like the ones of projectiles
+                Sensor,
+                ActiveEvents::COLLISION_EVENTS,
+                owner.collision_groups(friendly_fire),
+            ),
+            Damage(self.damage),
+            NotShadowCaster,
+            NotShadowReceiver,
+            (Projectile, owner, self.hit_volume),
+            Missile {
+                target: Some(target),
+                ..self.guidance
+            },
+            VisualInterpolation::default(),
+            Name::new("Missile"),
+        ));
+        if let Some(explosion) = self.explosion {
+            missile.insert(Explosive(explosion));
+        }
+    }
+}
+
+/// Seeker of a missile launcher tracking a target
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct SeekerLock {
+    pub(crate) target: Entity,
+    /// Seconds the target has been tracked
+    pub(crate) time: f32,
+}
+
+/// Guided missile homing in on the `target`
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct Missile {
+    target: Option<Entity>,
+    thrust: f32,
+    /// Seconds of engine burn left
+    fuel: f32,
+    /// Radians per second
+    turn_rate: f32,
+    navigation_constant: f32,
+    proximity: f32,
+}
+
+/// Candidate closest to the seeker axis within the cone of `max_angle` and the `range`
+pub(crate) fn seeker_candidate(
+    origin: Vec3,
+    forward: Vec3,
+    max_angle: f32,
+    range: f32,
+    candidates: impl IntoIterator<Item = (Entity, Vec3)>,
+) -> Option<Entity> {
+    candidates
+        .into_iter()
+        .filter(|(_, position)| position.distance(origin) <= range)
+        .map(|(entity, position)| (entity, forward.angle_between(position - origin)))
+        .filter(|(_, angle)| *angle <= max_angle)
+        .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)))
+        .map(|(entity, _)| entity)
+}
+
+/// Lock after a tick of `dt` with the seeker pointed at the `candidate`.
+/// Switching to another target starts locking over.
+pub(crate) fn update_lock(
+    lock: Option<SeekerLock>,
+    candidate: Option<Entity>,
+    dt: f32,
+) -> Option<SeekerLock> {
+    let target = candidate?;
+    Some(match lock {
+        Some(lock) if lock.target == target => SeekerLock {
+            target,
+            time: lock.time + dt,
+        },
+        _ => SeekerLock { target, time: 0.0 },
+    })
+}
+
+/// Proportional navigation: lateral acceleration rotating the missile `velocity`
+/// `navigation_constant` times faster than the line of sight to the target rotates.
+/// Target position and velocity are relative to the missile.
+pub(crate) fn proportional_navigation(
+    rel_position: Vec3,
+    rel_velocity: Vec3,
+    velocity: Vec3,
+    navigation_constant: f32,
+) -> Vec3 {
+    let distance_squared = rel_position.length_squared();
+    if distance_squared < 1e-6 {
+        return Vec3::ZERO;
+    }
+    let line_of_sight_rate = rel_position.cross(rel_velocity) / distance_squared;
+    navigation_constant * line_of_sight_rate.cross(velocity)
+}
+
+/// Direction of the engine `thrust` providing the `lateral` acceleration perpendicular to the `heading`,
+/// with the rest of the thrust spent on speeding up. Lateral acceleration is limited by the thrust.
+pub(crate) fn thrust_direction(lateral: Vec3, heading: Vec3, thrust: f32) -> Vec3 {
+    let lateral = lateral
+        .reject_from_normalized(heading)
+        .clamp_length_max(thrust);
+    let forward = (thrust * thrust - lateral.length_squared()).max(0.0).sqrt();
+    (lateral + heading * forward).normalize_or(heading)
+}
+
+/// Angular velocity turning `forward` towards `desired` during a tick of `dt`, limited by `max_rate`
+pub(crate) fn turn_towards(forward: Vec3, desired: Vec3, max_rate: f32, dt: f32) -> Vec3 {
+    let angle = forward.angle_between(desired);
+    if angle < 1e-4 {
+        return Vec3::ZERO;
+    }
+    // Any axis works to turn around
+    let axis = forward
+        .cross(desired)
+        .try_normalize()
+        .unwrap_or_else(|| forward.any_orthonormal_vector());
+    axis * (angle / dt).min(max_rate)
+}
+
+/// Closest distance to the target during the last tick of `dt`,
+/// given the target position and velocity relative to the missile at the end of the tick
+pub(crate) fn closest_approach(rel_position: Vec3, rel_velocity: Vec3, dt: f32) -> f32 {
+    let speed_squared = rel_velocity.length_squared();
+    let time = if speed_squared > 0.0 {
+        (-rel_position.dot(rel_velocity) / speed_squared).clamp(-dt, 0.0)
+    } else {
+        0.0
+    };
+    (rel_position + rel_velocity * time).length()
+}
+
+/// Tracks targets with launcher seekers and launches missiles at locked targets
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fire_missiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    weapon_types: Res<WeaponTypes>,
+    friendly_fire: Res<FriendlyFire>,
+    mut weapons: Query<(Entity, &mut Weapon, &GlobalTransform)>,
+    targets: Query<(Entity, &GlobalTransform, Option<&Faction>), With<Hull>>,
+    ships: Query<ShipMotion>,
+    factions: Query<&Faction>,
+    parents: Query<&Parent>,
+) {
+    let dt = time.delta_secs();
+    for (entity, mut weapon, transform) in weapons.iter_mut() {
+        let Some(WeaponType::Launcher(launcher)) = weapon_types.get(&weapon) else {
+            continue;
+        };
+        let is_firing = weapon.take_trigger();
+        weapon.cooldown = (weapon.cooldown - dt).max(0.0);
+
+        let origin = transform.translation();
+        let (shooter, launcher_velocity) = weapon_shooter(entity, origin, &parents, &ships);
+        let owner = ProjectileOwner::new(shooter, &factions);
+        let forward: Vec3 = transform.forward().into();
+        let candidate = seeker_candidate(
+            origin,
+            forward,
+            launcher.lock_angle,
+            launcher.lock_range,
+            targets
+                .iter()
+                .filter(|(target, _, faction)| {
+                    owner.can_hit(*target, faction.copied(), *friendly_fire)
+                })
+                .map(|(target, transform, _)| (target, transform.translation())),
+        );
+        weapon.lock = update_lock(weapon.lock, candidate, dt);
+
+        let Some(lock) = weapon.lock else {
+            continue;
+        };
+        if !is_firing || weapon.cooldown > 0.0 || lock.time < launcher.lock_time {
+            continue;
+        }
+        weapon.cooldown = launcher.reload * weapon.interval_scale;
+        launcher.spawn(
+            &mut commands,
+            origin,
+            forward,
+            launcher_velocity + forward * launcher.launch_speed,
+            owner,
+            *friendly_fire,
+            lock.target,
+        );
+    }
+}
+
+/// Steers missiles by proportional navigation and burns their fuel
+pub(crate) fn guide_missiles(
+    time: Res<Time>,
+    mut missiles: Query<(&mut Missile, &Transform, &mut Velocity)>,
+    targets: Query<(&GlobalTransform, Option<&Velocity>), Without<Missile>>,
+) {
+    let dt = time.delta_secs();
+    for (mut missile, transform, mut velocity) in missiles.iter_mut() {
+        let nose: Vec3 = transform.up().into();
+        let heading = velocity.linvel.try_normalize().unwrap_or(nose);
+        let thrust = if missile.fuel > 0.0 {
+            missile.thrust
+        } else {
+            0.0
+        };
+        // Flies straight once the target is lost
+        let desired = match missile.target.and_then(|target| targets.get(target).ok()) {
+            Some((target, target_velocity)) if thrust > 0.0 => {
+                let lateral = proportional_navigation(
+                    target.translation() - transform.translation,
+                    target_velocity.map(|v| v.linvel).unwrap_or_default() - velocity.linvel,
+                    velocity.linvel,
+                    missile.navigation_constant,
+                );
+                thrust_direction(lateral, heading, thrust)
+            }
+            _ => heading,
+        };
+        velocity.angvel = turn_towards(nose, desired, missile.turn_rate, dt);
+        if thrust > 0.0 {
+            velocity.linvel += nose * thrust * missile.fuel.min(dt);
+            missile.fuel -= dt;
+        }
+    }
+}
+
+/// Explodes missiles passing close enough to their targets.
+/// Runs after the physics step, so top-level `Transform`s are up to date.
+pub(crate) fn detect_proximity_hits(
+    time: Res<Time>,
+    missiles: Query<(
+        Entity,
+        &Missile,
+        &ProjectileOwner,
+        &Damage,
+        &Transform,
+        &Velocity,
+        &ReadMassProperties,
+    )>,
+    targets: Query<(&Transform, Option<&Velocity>), Without<Missile>>,
+    mut hits: ResMut<Events<ProjectileHit>>,
+) {
+    // Missiles touching their targets have already hit them during this tick
+    let spent: Vec<_> = hits
+        .iter_current_update_events()
+        .map(|hit| hit.projectile)
+        .collect();
+    for (entity, missile, owner, damage, transform, velocity, mass) in missiles.iter() {
+        let Some(target) = missile.target else {
+            continue;
+        };
+        let Ok((target_transform, target_velocity)) = targets.get(target) else {
+            continue;
+        };
+        if spent.contains(&entity) {
+            continue;
+        }
+        let rel_position = target_transform.translation - transform.translation;
+        let rel_velocity = target_velocity.map(|v| v.linvel).unwrap_or_default() - velocity.linvel;
+        if closest_approach(rel_position, rel_velocity, time.delta_secs()) > missile.proximity {
+            continue;
+        }
+        hits.send(ProjectileHit {
+            projectile: entity,
+            shooter: owner.ship,
+            target,
+            point: transform.translation,
+            normal: -rel_position.normalize_or_zero(),
+            damage: damage.0,
+            velocity: velocity.linvel,
+            mass: mass.get().mass,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        explosion::Explosion,
+        hull::Hull,
+        simulation::DEFAULT_TICK_RATE,
+        weapon::{
+            tests::{hold_triggers, run, ship, weapon_app, RecordedHits},
+            WeaponFireSet,
+        },
+    };
+
+    #[test]
+    fn missile_intercepts_crossing_target() {
+        let mut app = weapon_app();
+        app.add_systems(FixedUpdate, hold_triggers.before(WeaponFireSet));
+        let world = app.world_mut();
+        world
+            .spawn(ship(Vec3::ZERO))
+            .with_child((Transform::default(), Weapon::new("missile")));
+        // Crosses the launcher axis, aiming at its current position would miss
+        let target_velocity = Vec3::new(40.0, 0.0, 0.0);
+        let target = world
+            .spawn((
+                Transform::from_xyz(-20.0, 0.0, -300.0),
+                RigidBody::KinematicVelocityBased,
+                Collider::ball(3.0),
+                Velocity::linear(target_velocity),
+                Hull::new(1000.0),
+            ))
+            .id();
+
+        // Missile is launched once locked for 1.5 seconds and lives for 20
+        let lifetime_ticks = (21.5 * DEFAULT_TICK_RATE as f32) as usize;
+        let ticks = (0..lifetime_ticks)
+            .find(|_| {
+                app.update();
+                !app.world().resource::<RecordedHits>().0.is_empty()
+            })
+            .expect("missile missed the target");
+
+        let hit = &app.world().resource::<RecordedHits>().0[0];
+        assert_eq!(hit.target, target);
+        // Direct or proximity hit of the missile warhead
+        assert_eq!(hit.damage, 20.0);
+        let seconds = ticks as f32 / DEFAULT_TICK_RATE as f32;
+        let target_position = Vec3::new(-20.0, 0.0, -300.0) + target_velocity * seconds;
+        assert!(
+            hit.point.distance(target_position) < 5.0,
+            "{} at {seconds}",
+            hit.point
+        );
+    }
+
+    #[derive(Resource, Default)]
+    struct RecordedExplosions(Vec<Explosion>);
+
+    fn record_explosions(
+        mut explosions: EventReader<Explosion>,
+        mut recorded: ResMut<RecordedExplosions>,
+    ) {
+        recorded.0.extend(explosions.read().cloned());
+    }
+
+    #[test]
+    fn missile_self_destructs_after_missing() {
+        let mut app = weapon_app();
+        app.add_systems(FixedUpdate, hold_triggers.before(WeaponFireSet))
+            .init_resource::<RecordedExplosions>()
+            .add_systems(FixedUpdate, record_explosions);
+        let world = app.world_mut();
+        let shooter = world
+            .spawn(ship(Vec3::ZERO))
+            .with_child((Transform::default(), Weapon::new("missile")))
+            .id();
+        let target = world
+            .spawn((
+                Transform::from_xyz(0.0, 0.0, -300.0),
+                RigidBody::Fixed,
+                Collider::ball(3.0),
+                Hull::new(1000.0),
+            ))
+            .id();
+
+        // Target disappears right after the launch, so the missile has nothing to hit
+        let missile = loop {
+            app.update();
+            let world = app.world_mut();
+            let launched = world
+                .query_filtered::<Entity, With<Missile>>()
+                .iter(world)
+                .next();
+            if let Some(missile) = launched {
+                world.despawn(target);
+                break missile;
+            }
+        };
+        // Seeker can't lock on anything else, so no more missiles are launched
+        run(&mut app, 21.0);
+
+        let world = app.world();
+        assert!(world.get_entity(missile).is_err());
+        let explosions = &world.resource::<RecordedExplosions>().0;
+        assert_eq!(explosions.len(), 1);
+        assert_eq!(explosions[0].cause, shooter);
+        assert!(world.resource::<RecordedHits>().0.is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+use std::f64::consts::{PI, TAU};
+
+use bevy::{
+    math::{DMat3, DQuat, DVec3},
+    prelude::*,
+};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    gravity::GravitySource,
+    simulation::{SimulationTick, TickRate},
+    GameStates,
+};
+
+/// Moves entities with [`Orbit`] along their orbits. Positions are computed analytically
+/// from the simulation time, so orbits never drift. Orbiting entities are expected
+/// to be `RigidBody::KinematicPositionBased`, so rapier derives their velocity and ships can collide with them.
+pub(crate) struct OrbitPlugin;
+impl Plugin for OrbitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            propagate_orbits
+                .before(PhysicsSet::SyncBackend)
+                .run_if(in_state(GameStates::Next)),
+        );
+    }
+}
+
+/// Keplerian elements of an elliptic orbit. Inclination, longitude of the ascending node
+/// and argument of periapsis are stored together as the orbit orientation.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct OrbitalElements {
+    pub(crate) semi_major_axis: f64,
+    pub(crate) eccentricity: f64,
+    /// Mean anomaly at the epoch
+    pub(crate) mean_anomaly: f64,
+    /// Rotation from the perifocal frame (X to the periapsis, Z along the angular momentum) to the world
+    orientation: DQuat,
+}
+
+impl OrbitalElements {
+    /// Orbit of a body with the `position` and `velocity` relative to the primary with
+    /// the gravitational parameter `mu`. Returns `None` for escape trajectories
+    /// and degenerate radial ones, as they can't be described by an ellipse.
+    pub(crate) fn from_state_vectors(mu: f64, position: DVec3, velocity: DVec3) -> Option<Self> {
+        let r = position.length();
+        let momentum = position.cross(velocity);
+        let energy = velocity.length_squared() / 2.0 - mu / r;
+        if r == 0.0 || energy >= 0.0 || momentum.length_squared() < 1e-12 {
+            return None;
+        }
+
+        let eccentricity_vector = ((velocity.length_squared() - mu / r) * position
+            - position.dot(velocity) * velocity)
+            / mu;
+        let eccentricity = eccentricity_vector.length();
+        let normal = momentum.normalize();
+        // Periapsis of a circular orbit is undefined, so it's placed at the current position
+        let periapsis = if eccentricity > 1e-9 {
+            eccentricity_vector / eccentricity
+        } else {
+            position / r
+        };
+        let orientation = DQuat::from_mat3(&DMat3::from_cols(
+            periapsis,
+            normal.cross(periapsis),
+            normal,
+        ));
+
+        let true_anomaly = position
+            .dot(normal.cross(periapsis))
+            .atan2(position.dot(periapsis));
+        let eccentric_anomaly = ((1.0 - eccentricity * eccentricity).sqrt() * true_anomaly.sin())
+            .atan2(eccentricity + true_anomaly.cos());
+        Some(Self {
+            semi_major_axis: -mu / (2.0 * energy),
+            eccentricity,
+            mean_anomaly: eccentric_anomaly - eccentricity * eccentric_anomaly.sin(),
+            orientation,
+        })
+    }
+
+    pub(crate) fn periapsis(&self) -> f64 {
+        self.semi_major_axis * (1.0 - self.eccentricity)
+    }
+
+    pub(crate) fn apoapsis(&self) -> f64 {
+        self.semi_major_axis * (1.0 + self.eccentricity)
+    }
+
+    pub(crate) fn period(&self, mu: f64) -> f64 {
+        TAU / self.mean_motion(mu)
+    }
+
+    fn mean_motion(&self, mu: f64) -> f64 {
+        (mu / self.semi_major_axis.powi(3)).sqrt()
+    }
+
+    /// Position and velocity relative to the primary after `time` seconds from the epoch
+    pub(crate) fn state_at(&self, mu: f64, time: f64) -> (DVec3, DVec3) {
+        let a = self.semi_major_axis;
+        let e = self.eccentricity;
+        let mean_anomaly = self.mean_anomaly + self.mean_motion(mu) * time;
+        let eccentric_anomaly = solve_kepler(mean_anomaly, e);
+        let (sin, cos) = eccentric_anomaly.sin_cos();
+        let b = (1.0 - e * e).sqrt();
+
+        let r = a * (1.0 - e * cos);
+        let position = DVec3::new(a * (cos - e), a * b * sin, 0.0);
+        let speed = (mu * a).sqrt() / r;
+        let velocity = DVec3::new(-speed * sin, speed * b * cos, 0.0);
+        (self.orientation * position, self.orientation * velocity)
+    }
+}
+
+/// Solves Kepler's equation `E - e sin(E) = M` for the eccentric anomaly `E` of an elliptic orbit
+pub(crate) fn solve_kepler(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    // Wrapped to [-PI, PI) for fast convergence
+    let mean_anomaly = (mean_anomaly + PI).rem_euclid(TAU) - PI;
+    let mut anomaly = if eccentricity < 0.8 {
+        mean_anomaly
+    } else {
+        PI.copysign(mean_anomaly)
+    };
+    // Newton's method, converges in a few iterations for all elliptic orbits
+    for _ in 0..50 {
+        let error = anomaly - eccentricity * anomaly.sin() - mean_anomaly;
+        if error.abs() < 1e-12 {
+            break;
+        }
+        anomaly -= error / (1.0 - eccentricity * anomaly.cos());
+    }
+    anomaly
+}
+
+/// On-rails orbit around the `primary`, which should have a [`GravitySource`]
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct Orbit {
+    pub(crate) primary: Entity,
+    pub(crate) elements: OrbitalElements,
+    /// Simulation time of the elements in seconds
+    pub(crate) epoch: f64,
+}
+
+fn propagate_orbits(
+    tick: Res<SimulationTick>,
+    tick_rate: Res<TickRate>,
+    primaries: Query<(&GravitySource, &GlobalTransform)>,
+    mut orbits: Query<(&Orbit, &mut Transform)>,
+) {
+    // Kinematic bodies are moved to the position at the end of this tick
+    let time = (tick.0 + 1) as f64 / tick_rate.0 as f64;
+    for (orbit, mut transform) in orbits.iter_mut() {
+        let Ok((source, primary)) = primaries.get(orbit.primary) else {
+            continue;
+        };
+        let (position, _) = orbit
+            .elements
+            .state_at(source.mu() as f64, time - orbit.epoch);
+        transform.translation = primary.translation() + position.as_vec3();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MU: f64 = 1.0e4;
+
+    fn assert_close(actual: DVec3, expected: DVec3) {
+        assert!(
+            actual.distance(expected) < 1e-9 * expected.length().max(1.0),
+            "{actual} != {expected}"
+        );
+    }
+
+    #[test]
+    fn state_vectors_round_trip() {
+        let position = DVec3::new(120.0, 30.0, -50.0);
+        let velocity = DVec3::new(3.0, 5.0, 8.0);
+        let elements = OrbitalElements::from_state_vectors(MU, position, velocity).unwrap();
+
+        let (actual_position, actual_velocity) = elements.state_at(MU, 0.0);
+        assert_close(actual_position, position);
+        assert_close(actual_velocity, velocity);
+    }
+
+    #[test]
+    fn body_returns_after_period() {
+        let elements = OrbitalElements::from_state_vectors(
+            MU,
+            DVec3::new(-80.0, 10.0, 40.0),
+            DVec3::new(2.0, -7.0, 4.0),
+        )
+        .unwrap();
+        let period = elements.period(MU);
+
+        let (start, start_velocity) = elements.state_at(MU, 0.0);
+        let (half, _) = elements.state_at(MU, period / 2.0);
+        let (end, end_velocity) = elements.state_at(MU, period);
+        assert!(half.distance(start) > 10.0, "{half} {start}");
+        assert_close(end, start);
+        assert_close(end_velocity, start_velocity);
+    }
+
+    #[test]
+    fn kepler_equation_converges_at_high_eccentricity() {
+        let eccentricity = 0.95;
+        for mean_anomaly in [0.0, 1e-6, 0.01, -0.01, PI - 0.01, PI - 1e-6, -PI + 0.01, PI] {
+            let anomaly = solve_kepler(mean_anomaly, eccentricity);
+            let residual = anomaly - eccentricity * anomaly.sin() - mean_anomaly;
+            // Same angle up to full turns
+            let residual = (residual + PI).rem_euclid(TAU) - PI;
+            assert!(residual.abs() < 1e-10, "M = {mean_anomaly}: E = {anomaly}");
+        }
+    }
+
+    #[test]
+    fn inclined_ellipse_apsides() {
+        // Periapsis at 100 m and apoapsis at 200 m, inclined to the XZ plane
+        let periapsis = 100.0;
+        let semi_major_axis = 150.0;
+        let speed = (MU * (2.0 / periapsis - 1.0 / semi_major_axis)).sqrt();
+        let position = DVec3::new(periapsis, 0.0, 0.0);
+        let velocity = DVec3::new(0.0, 0.6, -0.8) * speed;
+        let elements = OrbitalElements::from_state_vectors(MU, position, velocity).unwrap();
+
+        assert!((elements.semi_major_axis - semi_major_axis).abs() < 1e-9);
+        assert!((elements.eccentricity - 1.0 / 3.0).abs() < 1e-9);
+        assert!((elements.periapsis() - 100.0).abs() < 1e-9);
+        assert!((elements.apoapsis() - 200.0).abs() < 1e-9);
+
+        // Half a period later the body is at the opposite side, at the apoapsis
+        let (apoapsis, _) = elements.state_at(MU, elements.period(MU) / 2.0);
+        assert_close(apoapsis, DVec3::new(-200.0, 0.0, 0.0));
+    }
+}
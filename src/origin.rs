@@ -0,0 +1,234 @@
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{simulation::VisualInterpolation, GameStates, Player};
+
+/// Distance of the player from the world origin after which the origin is moved to the player
+const REBASE_DISTANCE: f32 = 2000.0;
+
+/// Floating origin: keeps the player near the world origin, so `f32` transforms and rapier
+/// stay precise however far the player travels. Once the player is further than [`REBASE_DISTANCE`],
+/// the origin is moved to the player and all top-level transforms are shifted back.
+///
+/// Transforms are relative to the [`FloatingOrigin`], entities that need precise positions
+/// in the whole universe should have [`UniversePosition`].
+pub(crate) struct OriginPlugin;
+impl Plugin for OriginPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FloatingOrigin>().add_systems(
+            FixedPostUpdate,
+            (update_universe_positions, rebase_origin)
+                .chain()
+                .run_if(in_state(GameStates::Next)),
+        );
+    }
+}
+
+/// Universe position of the world origin
+#[derive(Resource, Default, Clone, Copy, PartialEq, Debug)]
+pub(crate) struct FloatingOrigin(pub(crate) DVec3);
+
+impl FloatingOrigin {
+    /// World translation of the universe `position`
+    pub(crate) fn to_world(self, position: DVec3) -> Vec3 {
+        (position - self.0).as_vec3()
+    }
+
+    /// Universe position of the world `translation`
+    pub(crate) fn to_universe(self, translation: Vec3) -> DVec3 {
+        self.0 + translation.as_dvec3()
+    }
+}
+
+/// Authoritative position of the top-level entity in the universe. After each simulation tick
+/// it is moved in `f64` by the distance the entity travelled in `Transform`, so resting entities
+/// keep their exact position however far they are, and moving ones don't lose precision of the whole
+/// position on every tick. Used to restore precise `Transform` after the origin is moved.
+///
+/// Entities should be spawned with `Transform` at [`FloatingOrigin::to_world`] of this position.
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub(crate) struct UniversePosition(pub(crate) DVec3);
+
+/// World translation of the top-level entity after the origin is moved from `old` to `new`.
+/// Entities with universe position are placed precisely, others are shifted
+/// by the origin movement, so positions relative to each other are kept.
+pub(crate) fn rebased_translation(
+    translation: Vec3,
+    universe_position: Option<DVec3>,
+    old: FloatingOrigin,
+    new: FloatingOrigin,
+) -> Vec3 {
+    match universe_position {
+        Some(position) => new.to_world(position),
+        None => translation - (new.0 - old.0).as_vec3(),
+    }
+}
+
+fn update_universe_positions(
+    origin: Res<FloatingOrigin>,
+    mut query: Query<(&Transform, &mut UniversePosition), Without<Parent>>,
+) {
+    for (transform, mut position) in query.iter_mut() {
+        // `Transform` matched the position after the last update, unless the entity moved since then
+        let travelled = transform.translation - origin.to_world(position.0);
+        if travelled != Vec3::ZERO {
+            position.0 += travelled.as_dvec3();
+        }
+    }
+}
+
+/// Moves the origin to the player. Only translations are changed, so velocities are kept as is.
+/// Rapier sees changed transforms and moves bodies accordingly.
+#[allow(clippy::type_complexity)]
+fn rebase_origin(
+    mut origin: ResMut<FloatingOrigin>,
+    player: Query<Entity, With<Player>>,
+    mut query: Query<
+        (
+            &mut Transform,
+            Option<&UniversePosition>,
+            Option<&mut VisualInterpolation>,
+        ),
+        Without<Parent>,
+    >,
+) {
+    let Some(player) = player
+        .get_single()
+        .ok()
+        .and_then(|player| query.get(player).ok())
+        .map(|(transform, _, _)| transform.translation)
+    else {
+        return;
+    };
+    if player.length() < REBASE_DISTANCE {
+        return;
+    }
+
+    let old = *origin;
+    let new = FloatingOrigin(old.to_universe(player));
+    for (mut transform, position, interpolation) in query.iter_mut() {
+        let translation = rebased_translation(
+            transform.translation,
+            position.map(|position| position.0),
+            old,
+            new,
+        );
+        // Interpolation should continue from the shifted position instead of flying across the world
+        if let Some(mut interpolation) = interpolation {
+            interpolation.shift(translation - transform.translation);
+        }
+        transform.translation = translation;
+    }
+    *origin = new;
+    debug!("Floating origin moved to {}", new.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_rapier3d::prelude::*;
+
+    use super::*;
+    use crate::simulation::test_app;
+
+    #[test]
+    fn rebased_translation_keeps_relative_positions() {
+        let old = FloatingOrigin(DVec3::new(1.0e6, 0.0, -2.0e6));
+        let new = FloatingOrigin(DVec3::new(1.0e6 + 2500.0, 100.0, -2.0e6));
+        let a = Vec3::new(2500.0, 100.0, 0.0);
+        let b = Vec3::new(2400.0, -50.0, 30.0);
+
+        let shifted_a = rebased_translation(a, None, old, new);
+        let shifted_b = rebased_translation(b, None, old, new);
+        assert_eq!(shifted_a, Vec3::ZERO);
+        assert!((shifted_b - shifted_a).distance(b - a) < 1e-3);
+
+        // Precise universe position gives the same result as the shift
+        let precise_b = rebased_translation(Vec3::ZERO, Some(old.to_universe(b)), old, new);
+        assert!(precise_b.distance(shifted_b) < 1e-3);
+        assert_eq!(new.to_universe(precise_b), old.to_universe(b));
+    }
+
+    #[test]
+    fn universe_position_of_resting_entity_is_exact() {
+        let mut app = test_app();
+        app.add_plugins(OriginPlugin);
+        // Not representable in `f32` at this distance
+        let position = DVec3::new(1.0e7 + 0.125, 0.0, 0.0);
+        let origin = FloatingOrigin::default();
+        let entity = app
+            .world_mut()
+            .spawn((
+                Transform::from_translation(origin.to_world(position)),
+                UniversePosition(position),
+            ))
+            .id();
+        for _ in 0..10 {
+            app.update();
+        }
+        let kept = app.world().get::<UniversePosition>(entity).unwrap().0;
+        assert_eq!(kept, position);
+    }
+
+    #[test]
+    fn rebase_origin_keeps_relative_positions_and_velocities() {
+        let mut app = test_app();
+        app.add_plugins(OriginPlugin);
+        let player_velocity = Vec3::new(10.0, 0.0, 0.0);
+        let other_velocity = Vec3::new(0.0, 5.0, 0.0);
+        let body = |position: Vec3, velocity: Vec3| {
+            (
+                Transform::from_translation(position),
+                UniversePosition(position.as_dvec3()),
+                RigidBody::Dynamic,
+                Collider::ball(1.0),
+                Velocity::linear(velocity),
+            )
+        };
+        let world = app.world_mut();
+        let player = world
+            .spawn((Player, body(Vec3::new(2500.0, 0.0, 0.0), player_velocity)))
+            .id();
+        let other = world
+            .spawn(body(Vec3::new(2600.0, 0.0, 50.0), other_velocity))
+            .id();
+        // Without universe position it's shifted as is
+        let plain = world.spawn(Transform::from_xyz(2500.0, 10.0, 0.0)).id();
+
+        app.update();
+        let world = app.world();
+        let origin = *world.resource::<FloatingOrigin>();
+        assert_ne!(origin, FloatingOrigin::default());
+        let translation = |entity| world.get::<Transform>(entity).unwrap().translation;
+        let dt = 1.0 / crate::simulation::DEFAULT_TICK_RATE as f32;
+        let relative = translation(other) - translation(player);
+        let expected = Vec3::new(100.0, 0.0, 50.0) + (other_velocity - player_velocity) * dt;
+        assert!(
+            relative.distance(expected) < 1e-3,
+            "{relative} != {expected}"
+        );
+        assert!(
+            (translation(plain) - translation(player))
+                .distance(Vec3::new(0.0, 10.0, 0.0) - player_velocity * dt)
+                < 1e-3
+        );
+        assert!(
+            origin
+                .to_universe(translation(player))
+                .distance(world.get::<UniversePosition>(player).unwrap().0)
+                < 1e-3
+        );
+
+        // Rapier keeps moving bodies with the same velocities from the new positions
+        app.update();
+        let world = app.world();
+        let velocity = |entity| world.get::<Velocity>(entity).unwrap().linvel;
+        assert!(velocity(player).distance(player_velocity) < 1e-4);
+        assert!(velocity(other).distance(other_velocity) < 1e-4);
+        let translation = |entity| world.get::<Transform>(entity).unwrap().translation;
+        let relative = translation(other) - translation(player);
+        let expected = Vec3::new(100.0, 0.0, 50.0) + (other_velocity - player_velocity) * 2.0 * dt;
+        assert!(
+            relative.distance(expected) < 1e-3,
+            "{relative} != {expected}"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+
+use crate::{weapon::WeaponFireSet, GameStates};
+
+/// Ships store energy in a [`PowerPool`] that weapons draw from and that recharges over time
+pub(crate) struct PowerPlugin;
+impl Plugin for PowerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            recharge_power
+                .before(WeaponFireSet)
+                .run_if(in_state(GameStates::Next)),
+        );
+    }
+}
+
+/// Ship energy storage
+#[derive(Component, Clone, Debug)]
+pub(crate) struct PowerPool {
+    energy: f32,
+    capacity: f32,
+    /// Energy restored per second
+    recharge_rate: f32,
+}
+
+impl PowerPool {
+    /// Fully charged pool
+    pub(crate) fn new(capacity: f32, recharge_rate: f32) -> Self {
+        Self {
+            energy: capacity,
+            capacity,
+            recharge_rate,
+        }
+    }
+
+    pub(crate) fn energy(&self) -> f32 {
+        self.energy
+    }
+
+    pub(crate) fn capacity(&self) -> f32 {
+        self.capacity
+    }
+
+    /// Takes the `amount` of energy, returns `false` and keeps the energy if there is not enough of it
+    pub(crate) fn draw(&mut self, amount: f32) -> bool {
+        if self.energy < amount {
+            return false;
+        }
+        self.energy -= amount;
+        true
+    }
+
+    fn recharge(&mut self, dt: f32) {
+        self.energy = (self.energy + self.recharge_rate * dt).min(self.capacity);
+    }
+}
+
+fn recharge_power(time: Res<Time>, mut pools: Query<&mut PowerPool>) {
+    for mut pool in pools.iter_mut() {
+        pool.recharge(time.delta_secs());
+    }
+}
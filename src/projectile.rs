@@ -0,0 +1,446 @@
+use bevy::{prelude::*, utils::Parallel};
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    faction::FriendlyFire,
+    weapon::{hit_entity, Damage, ProjectileHit, ProjectileOwner},
+};
+
+/// How a projectile type finds hits along the path travelled during each tick.
+/// Both are swept, so even the fastest rounds can't pass through thin plates.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) enum HitDetection {
+    /// Ray along the path of the projectile center, the cheapest one
+    #[default]
+    Ray,
+    /// Sphere of the projectile radius cast along the path, so large rounds hit with their edge
+    Shape,
+}
+
+/// Gun projectile simulated without rapier: it flies with a constant velocity and hits the first
+/// solid collider on the path travelled during a tick. Spent bullets are hidden and kept in the
+/// [`ProjectilePool`] to be reused by next shots instead of being despawned.
+#[derive(Component, Clone, Debug)]
+pub(crate) struct Bullet {
+    velocity: Vec3,
+    /// Seconds left before the bullet is spent if it doesn't hit anything
+    lifetime: f32,
+    mass: f32,
+    /// Shape cast along the path, the path is ray cast without it, see [`HitDetection`]
+    shape: Option<Collider>,
+    /// Cleared once the bullet is returned to the pool
+    live: bool,
+}
+
+impl Bullet {
+    pub(crate) fn new(velocity: Vec3, lifetime: f32, mass: f32) -> Self {
+        Self {
+            velocity,
+            lifetime,
+            mass,
+            shape: None,
+            live: true,
+        }
+    }
+
+    /// Finds hits with a sphere of the `radius` instead of a ray
+    pub(crate) fn with_shape_cast(mut self, radius: f32) -> Self {
+        self.shape = Some(Collider::ball(radius));
+        self
+    }
+
+    /// Whether the bullet is flying, as opposed to waiting in the pool
+    pub(crate) fn is_live(&self) -> bool {
+        self.live
+    }
+}
+
+/// Sphere around a projectile that gun bullets can hit, so point defense can shoot it down.
+/// Meant for missiles and other projectiles simulated by rapier, bullets themselves can't be hit.
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct HitVolume {
+    pub(crate) radius: f32,
+}
+
+/// Time within `dt` at which a point moving from `rel_position` with `rel_velocity`,
+/// both relative to the center of a sphere of the `radius`, enters the sphere
+pub(crate) fn sweep_sphere(
+    rel_position: Vec3,
+    rel_velocity: Vec3,
+    radius: f32,
+    dt: f32,
+) -> Option<f32> {
+    let outside = rel_position.length_squared() - radius * radius;
+    if outside <= 0.0 {
+        return Some(0.0);
+    }
+    let approach = rel_position.dot(rel_velocity);
+    let speed_squared = rel_velocity.length_squared();
+    if approach >= 0.0 || speed_squared <= 0.0 {
+        return None;
+    }
+    let discriminant = approach * approach - speed_squared * outside;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let time = (-approach - discriminant.sqrt()) / speed_squared;
+    (time <= dt).then_some(time)
+}
+
+/// Projectile with a [`HitVolume`] bullets can hit during the tick
+struct VolumeTarget {
+    entity: Entity,
+    /// Center at the start of the tick
+    position: Vec3,
+    velocity: Vec3,
+    radius: f32,
+    owner: ProjectileOwner,
+}
+
+/// Bullet entities that are ready to be reused
+#[derive(Resource, Default)]
+pub(crate) struct ProjectilePool {
+    free: Vec<Entity>,
+    /// Number of bullets in flight
+    live: usize,
+}
+
+impl ProjectilePool {
+    pub(crate) fn live(&self) -> usize {
+        self.live
+    }
+
+    /// Takes a free bullet entity or spawns a new one. The caller should insert a [`Bullet`]
+    /// and the rest of the projectile components, replacing the ones left from the previous shot.
+    pub(crate) fn acquire<'a>(&mut self, commands: &'a mut Commands) -> EntityCommands<'a> {
+        self.live += 1;
+        match self.free.pop() {
+            Some(entity) => commands.entity(entity),
+            None => commands.spawn_empty(),
+        }
+    }
+
+    /// Hides the bullet and returns it to the pool, does nothing if it's already there
+    pub(crate) fn release(
+        &mut self,
+        entity: Entity,
+        bullet: &mut Bullet,
+        visibility: &mut Visibility,
+    ) {
+        if !bullet.live {
+            return;
+        }
+        bullet.live = false;
+        *visibility = Visibility::Hidden;
+        self.free.push(entity);
+        self.live -= 1;
+    }
+}
+
+/// Casts the path each live bullet travels during the tick and moves it, or reports a hit.
+/// Bullets also hit projectiles with a [`HitVolume`] of other owners, whichever is hit first counts.
+/// Runs after the physics step, so bullets are checked against the same state as other projectiles.
+/// Bullets are processed in parallel, hits and expirations are then sorted to keep the simulation deterministic.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn advance_projectiles(
+    time: Res<Time>,
+    rapier: ReadDefaultRapierContext,
+    friendly_fire: Res<FriendlyFire>,
+    mut pool: ResMut<ProjectilePool>,
+    mut bullets: Query<(
+        Entity,
+        &mut Bullet,
+        &mut Transform,
+        &mut Visibility,
+        &ProjectileOwner,
+        &Damage,
+    )>,
+    volumes: Query<(Entity, &Transform, &Velocity, &HitVolume, &ProjectileOwner), Without<Bullet>>,
+    bodies: Query<(), With<RigidBody>>,
+    parents: Query<&Parent>,
+    mut hits: EventWriter<ProjectileHit>,
+    mut found: Local<Parallel<Vec<ProjectileHit>>>,
+    mut expired: Local<Parallel<Vec<Entity>>>,
+) {
+    let rapier = rapier.single();
+    let dt = time.delta_secs();
+    // Projectiles have already moved during the step, bullets are about to
+    let targets: Vec<_> = volumes
+        .iter()
+        .map(
+            |(entity, transform, velocity, volume, owner)| VolumeTarget {
+                entity,
+                position: transform.translation - velocity.linvel * dt,
+                velocity: velocity.linvel,
+                radius: volume.radius,
+                owner: *owner,
+            },
+        )
+        .collect();
+    bullets
+        .par_iter_mut()
+        .for_each(|(entity, mut bullet, mut transform, _, owner, damage)| {
+            if !bullet.live {
+                return;
+            }
+            let origin = transform.translation;
+            let mut hit = None;
+            // Missiles are sensors, so only solid objects are hit by the cast
+            let filter = owner.query_filter(*friendly_fire);
+            if let Some(shape) = &bullet.shape {
+                let options = ShapeCastOptions {
+                    compute_impact_geometry_on_penetration: true,
+                    ..ShapeCastOptions::with_max_time_of_impact(dt)
+                };
+                hit = rapier
+                    .cast_shape(
+                        origin,
+                        Quat::IDENTITY,
+                        bullet.velocity,
+                        shape,
+                        options,
+                        filter,
+                    )
+                    .map(|(collider, hit)| {
+                        let center = origin + bullet.velocity * hit.time_of_impact;
+                        // The first witness and normal are on the hit collider, in world space
+                        let (point, normal) = hit
+                            .details
+                            .map_or((center, -bullet.velocity.normalize_or_zero()), |details| {
+                                (details.witness1, details.normal1)
+                            });
+                        (
+                            hit.time_of_impact,
+                            hit_entity(collider, &bodies, &parents),
+                            point,
+                            normal,
+                        )
+                    });
+            } else if let Some(direction) = bullet.velocity.try_normalize() {
+                let speed = bullet.velocity.length();
+                hit = rapier
+                    .cast_ray_and_get_normal(origin, direction, speed * dt, true, filter)
+                    .map(|(collider, hit)| {
+                        (
+                            hit.time_of_impact / speed,
+                            hit_entity(collider, &bodies, &parents),
+                            hit.point,
+                            hit.normal,
+                        )
+                    });
+            }
+            for target in targets
+                .iter()
+                .filter(|target| owner.can_hit_projectile(&target.owner, *friendly_fire))
+            {
+                let Some(time) = sweep_sphere(
+                    origin - target.position,
+                    bullet.velocity - target.velocity,
+                    target.radius,
+                    dt,
+                ) else {
+                    continue;
+                };
+                if hit.is_none_or(|(first, ..)| time < first) {
+                    let point = origin + bullet.velocity * time;
+                    let center = target.position + target.velocity * time;
+                    let normal = (point - center).normalize_or_zero();
+                    hit = Some((time, target.entity, point, normal));
+                }
+            }
+            if let Some((_, target, point, normal)) = hit {
+                found.borrow_local_mut().push(ProjectileHit {
+                    projectile: entity,
+                    shooter: owner.ship,
+                    target,
+                    point,
+                    normal,
+                    damage: damage.0,
+                    velocity: bullet.velocity,
+                    mass: bullet.mass,
+                });
+                return;
+            }
+            transform.translation += bullet.velocity * dt;
+            bullet.lifetime -= dt;
+            if bullet.lifetime <= 0.0 {
+                expired.borrow_local_mut().push(entity);
+            }
+        });
+
+    let mut new_hits = Vec::new();
+    found.drain_into(&mut new_hits);
+    new_hits.sort_by_key(|hit| hit.projectile);
+    hits.send_batch(new_hits);
+
+    let mut spent = Vec::new();
+    expired.drain_into(&mut spent);
+    spent.sort();
+    for entity in spent {
+        let (_, mut bullet, _, mut visibility, _, _) = bullets.get_mut(entity).unwrap();
+        pool.release(entity, &mut bullet, &mut visibility);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        faction::Faction,
+        weapon::{
+            tests::{run, weapon_app, RecordedHits},
+            Projectile,
+        },
+    };
+
+    /// Thin plate facing Z, its front surface is at `z + 0.1`
+    fn plate(z: f32) -> impl Bundle {
+        (
+            Transform::from_xyz(0.0, 0.0, z),
+            RigidBody::Fixed,
+            Collider::cuboid(5.0, 5.0, 0.1),
+        )
+    }
+
+    fn fire(world: &mut World, position: Vec3, bullet: Bullet) -> Entity {
+        let bullet = world.resource_scope(|world, mut pool: Mut<ProjectilePool>| {
+            pool.acquire(&mut world.commands())
+                .insert((
+                    Transform::from_translation(position),
+                    Visibility::Inherited,
+                    bullet,
+                    Damage(1.0),
+                    Projectile,
+                    ProjectileOwner::default(),
+                ))
+                .id()
+        });
+        world.flush();
+        bullet
+    }
+
+    #[test]
+    fn fast_round_does_not_tunnel_through_thin_plate() {
+        let mut app = weapon_app();
+        let world = app.world_mut();
+        let plate = world.spawn(plate(-50.0)).id();
+        // Travels more than 31 units per tick, a hundred times the plate thickness
+        let velocity = Vec3::new(0.0, 0.0, -2000.0);
+        let round = fire(world, Vec3::ZERO, Bullet::new(velocity, 1.0, 0.0));
+        // Plate edge is between two positions of this one, which is not at an exact tick distance
+        let offset = fire(
+            world,
+            Vec3::new(4.9, 0.0, 7.3),
+            Bullet::new(velocity, 1.0, 0.0),
+        );
+
+        run(&mut app, 0.25);
+
+        let hits = &app.world().resource::<RecordedHits>().0;
+        for round in [round, offset] {
+            let hit = hits
+                .iter()
+                .find(|hit| hit.projectile == round)
+                .expect("round passed through the plate");
+            assert_eq!(hit.target, plate);
+            // Hit at the sub-step of the tick when the round reached the surface
+            assert!((hit.point.z + 49.9).abs() < 1e-3, "{}", hit.point);
+            assert!(hit.normal.distance(Vec3::Z) < 1e-3, "{}", hit.normal);
+        }
+    }
+
+    #[test]
+    fn shape_cast_hits_with_projectile_edge() {
+        let mut app = weapon_app();
+        let world = app.world_mut();
+        let plate = world.spawn(plate(-50.0)).id();
+        // Both pass 0.3 units beside the plate edge
+        let velocity = Vec3::new(0.0, 0.0, -2000.0);
+        let ray = fire(
+            world,
+            Vec3::new(5.3, 0.0, 0.0),
+            Bullet::new(velocity, 1.0, 0.0),
+        );
+        let shape = fire(
+            world,
+            Vec3::new(0.0, 5.3, 0.0),
+            Bullet::new(velocity, 1.0, 0.0).with_shape_cast(0.5),
+        );
+
+        run(&mut app, 0.25);
+
+        let hits = &app.world().resource::<RecordedHits>().0;
+        assert!(hits.iter().all(|hit| hit.projectile != ray));
+        let hit = hits
+            .iter()
+            .find(|hit| hit.projectile == shape)
+            .expect("shape cast missed");
+        assert_eq!(hit.target, plate);
+        // Touches the plate edge with the lower side of the sphere
+        assert!(
+            hit.point.distance(Vec3::new(0.0, 5.0, -49.9)) < 0.5,
+            "{}",
+            hit.point
+        );
+    }
+
+    #[test]
+    fn bullets_hit_projectiles_of_other_owners() {
+        for friendly_fire in [FriendlyFire::Off, FriendlyFire::On] {
+            let mut app = weapon_app();
+            app.insert_resource(friendly_fire);
+            let world = app.world_mut();
+            let [shooter, ally, enemy] = [(); 3].map(|_| world.spawn_empty().id());
+            let owner = |ship, faction| ProjectileOwner {
+                ship: Some(ship),
+                faction: Some(faction),
+            };
+            // One lane per projectile owner, each crossed by a single bullet
+            let lanes = [
+                owner(shooter, Faction::Alliance),
+                owner(ally, Faction::Alliance),
+                owner(enemy, Faction::Raiders),
+            ];
+            let mut shots = Vec::new();
+            for (lane, target_owner) in lanes.into_iter().enumerate() {
+                let x = lane as f32 * 10.0;
+                let target = world
+                    .spawn((
+                        Transform::from_xyz(x, 0.0, -50.0),
+                        Velocity::zero(),
+                        HitVolume { radius: 1.0 },
+                        target_owner,
+                    ))
+                    .id();
+                let bullet = fire(
+                    world,
+                    Vec3::new(x, 0.0, 0.0),
+                    Bullet::new(Vec3::new(0.0, 0.0, -500.0), 1.0, 0.0),
+                );
+                world
+                    .entity_mut(bullet)
+                    .insert(owner(shooter, Faction::Alliance));
+                shots.push((bullet, target));
+            }
+
+            run(&mut app, 0.25);
+
+            let hits = &app.world().resource::<RecordedHits>().0;
+            let hit = |(bullet, target): (Entity, Entity)| {
+                hits.iter()
+                    .any(|hit| hit.projectile == bullet && hit.target == target)
+            };
+            assert!(!hit(shots[0]), "own projectile hit with {friendly_fire:?}");
+            assert_eq!(
+                hit(shots[1]),
+                friendly_fire == FriendlyFire::On,
+                "allied projectile with {friendly_fire:?}"
+            );
+            assert!(
+                hit(shots[2]),
+                "enemy projectile missed with {friendly_fire:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,478 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::AppExit, math::DVec3, prelude::*, time::TimeUpdateStrategy};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    origin::FloatingOrigin,
+    projectile::Bullet,
+    simulation::{SimulationRng, SimulationTick, TickInput, TickRate},
+    weapon::Projectile,
+    GameStates,
+};
+
+/// Recorded session file starts with this magic followed by the format version
+const MAGIC: &[u8; 4] = b"OFRP";
+const VERSION: u16 = 2;
+
+/// Recorded file header
+struct Header {
+    seed: u64,
+    tick_rate: u32,
+}
+
+impl Header {
+    fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&self.seed.to_le_bytes())?;
+        out.write_all(&self.tick_rate.to_le_bytes())
+    }
+
+    fn read(input: &mut impl Read) -> std::io::Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u16(input)? != VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "not a recorded session or unsupported version",
+            ));
+        }
+        Ok(Self {
+            seed: read_u64(input)?,
+            tick_rate: read_u32(input)?,
+        })
+    }
+}
+
+/// Input that was used starting from the `tick`. To keep file compact, records are written
+/// only when the input changes. The last record repeats the final input and marks the end of the session.
+///
+/// Layout: tick `u32`, actions `u32`, cursor flag `u8` and, if the flag is set, cursor offset `2 x f32`.
+#[derive(Clone)]
+struct Record {
+    tick: u32,
+    input: TickInput,
+}
+
+impl Record {
+    fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        out.write_all(&self.tick.to_le_bytes())?;
+        out.write_all(&self.input.actions.to_le_bytes())?;
+        match self.input.cursor_offset {
+            Some(offset) => {
+                out.write_all(&[1])?;
+                out.write_all(&offset.x.to_le_bytes())?;
+                out.write_all(&offset.y.to_le_bytes())
+            }
+            None => out.write_all(&[0]),
+        }
+    }
+
+    /// Reads the next record, `None` if there are no more records
+    fn read(input: &mut impl Read) -> std::io::Result<Option<Self>> {
+        let mut tick = [0; 4];
+        match input.read_exact(&mut tick) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let actions = read_u32(input)?;
+        let mut flag = [0; 1];
+        input.read_exact(&mut flag)?;
+        let cursor_offset = if flag[0] != 0 {
+            let x = f32::from_bits(read_u32(input)?);
+            let y = f32::from_bits(read_u32(input)?);
+            Some(Vec2::new(x, y))
+        } else {
+            None
+        };
+        Ok(Some(Self {
+            tick: u32::from_le_bytes(tick),
+            input: TickInput {
+                actions,
+                cursor_offset,
+            },
+        }))
+    }
+}
+
+fn read_u16(input: &mut impl Read) -> std::io::Result<u16> {
+    let mut bytes = [0; 2];
+    input.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(input: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Writes [`TickInput`] of each simulation tick into the file
+pub(crate) struct RecordPlugin(pub(crate) PathBuf);
+impl Plugin for RecordPlugin {
+    fn build(&self, app: &mut App) {
+        let file = File::create(&self.0)
+            .unwrap_or_else(|err| panic!("Failed to create {}: {err}", self.0.display()));
+        app.insert_resource(Recorder {
+            file: Some(file),
+            last: None,
+        })
+        .add_systems(Startup, write_header)
+        // Input is recorded after the tick, when it's known which tick index was used
+        .add_systems(
+            FixedPostUpdate,
+            record_tick_input.run_if(in_state(GameStates::Next)),
+        )
+        .add_systems(Last, finish_recording.run_if(on_event::<AppExit>));
+    }
+}
+
+#[derive(Resource)]
+struct Recorder {
+    /// Closed on write errors to not spam the log
+    file: Option<File>,
+    last: Option<Record>,
+}
+
+impl Recorder {
+    fn write(&mut self, write: impl FnOnce(&mut File) -> std::io::Result<()>) {
+        if let Some(file) = self.file.as_mut() {
+            if let Err(err) = write(file) {
+                error!("Failed to write recorded session: {err}");
+                self.file = None;
+            }
+        }
+    }
+}
+
+fn write_header(mut recorder: ResMut<Recorder>, rng: Res<SimulationRng>, tick_rate: Res<TickRate>) {
+    let header = Header {
+        seed: rng.seed(),
+        tick_rate: tick_rate.0,
+    };
+    recorder.write(|file| header.write(file));
+}
+
+fn record_tick_input(
+    mut recorder: ResMut<Recorder>,
+    tick: Res<SimulationTick>,
+    input: Res<TickInput>,
+) {
+    // Files are written directly without buffering, so the session is kept even if the game crashes
+    if recorder
+        .last
+        .as_ref()
+        .is_none_or(|last| last.input != *input)
+    {
+        let record = Record {
+            tick: tick.0,
+            input: *input,
+        };
+        recorder.write(|file| record.write(file));
+        recorder.last = Some(record);
+    } else if let Some(last) = recorder.last.as_mut() {
+        last.tick = tick.0;
+    }
+}
+
+fn finish_recording(mut recorder: ResMut<Recorder>) {
+    if let Some(last) = recorder.last.take() {
+        recorder.write(|file| last.write(file));
+    }
+}
+
+/// Drives the simulation from the recorded file and prints the final state once it's over.
+/// Expected to be used in a headless app, as time is advanced by exactly one tick per update.
+/// Simulation should run with the [`ReplayPlugin::tick_rate`] used for recording.
+pub(crate) struct ReplayPlugin {
+    header: Header,
+    records: Vec<Record>,
+}
+
+impl ReplayPlugin {
+    /// Reads the recorded session. Panics if the file cannot be read, as there is nothing to run.
+    pub(crate) fn load(path: &Path) -> Self {
+        File::open(path)
+            .and_then(|file| Self::read(&mut BufReader::new(file)))
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", path.display()))
+    }
+
+    fn read(input: &mut impl Read) -> std::io::Result<Self> {
+        let header = Header::read(input)?;
+        let mut records = Vec::new();
+        while let Some(record) = Record::read(input)? {
+            records.push(record);
+        }
+        Ok(Self { header, records })
+    }
+
+    pub(crate) fn tick_rate(&self) -> u32 {
+        self.header.tick_rate
+    }
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(
+            TickRate(self.header.tick_rate).duration(),
+        ))
+        .insert_resource(SimulationRng::new(self.header.seed))
+        .insert_resource(Replay {
+            records: self.records.clone(),
+            next: 0,
+        })
+        .add_systems(
+            FixedPreUpdate,
+            replay_tick_input.run_if(in_state(GameStates::Next)),
+        )
+        .add_systems(
+            FixedPostUpdate,
+            finish_replay.run_if(in_state(GameStates::Next)),
+        );
+    }
+}
+
+#[derive(Resource)]
+struct Replay {
+    records: Vec<Record>,
+    /// Index of the next record to apply
+    next: usize,
+}
+
+fn replay_tick_input(
+    mut replay: ResMut<Replay>,
+    tick: Res<SimulationTick>,
+    mut input: ResMut<TickInput>,
+) {
+    while let Some(record) = replay.records.get(replay.next) {
+        if record.tick > tick.0 {
+            break;
+        }
+        *input = record.input;
+        replay.next += 1;
+    }
+}
+
+/// Final state of the replayed session
+#[derive(Resource, PartialEq, Debug)]
+struct ReplaySummary {
+    /// Number of simulated ticks
+    ticks: u32,
+    /// Name, universe position and velocity of each ship, sorted by name
+    ships: Vec<(String, DVec3, Vec3)>,
+    /// Universe positions of flying projectiles, sorted by coordinates.
+    /// Bullets waiting in the pool are not counted.
+    projectiles: Vec<DVec3>,
+}
+
+/// Ships and other simulated bodies, everything except projectiles
+type SummaryShips<'w, 's> = Query<
+    'w,
+    's,
+    (&'static Name, &'static Transform, Option<&'static Velocity>),
+    (With<RigidBody>, Without<Projectile>),
+>;
+type SummaryProjectiles<'w, 's> =
+    Query<'w, 's, (&'static Transform, Option<&'static Bullet>), With<Projectile>>;
+
+impl ReplaySummary {
+    fn new(
+        tick: SimulationTick,
+        origin: &FloatingOrigin,
+        ships: &SummaryShips,
+        projectiles: &SummaryProjectiles,
+    ) -> Self {
+        let mut ships: Vec<_> = ships
+            .iter()
+            .map(|(name, transform, velocity)| {
+                (
+                    name.to_string(),
+                    origin.to_universe(transform.translation),
+                    velocity.map(|v| v.linvel).unwrap_or_default(),
+                )
+            })
+            .collect();
+        ships.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut projectiles: Vec<_> = projectiles
+            .iter()
+            .filter(|(_, bullet)| bullet.is_none_or(|bullet| bullet.is_live()))
+            .map(|(transform, _)| origin.to_universe(transform.translation))
+            .collect();
+        projectiles.sort_by(|a, b| {
+            a.x.total_cmp(&b.x)
+                .then(a.y.total_cmp(&b.y))
+                .then(a.z.total_cmp(&b.z))
+        });
+        Self {
+            ticks: tick.0 + 1,
+            ships,
+            projectiles,
+        }
+    }
+}
+
+fn finish_replay(
+    mut commands: Commands,
+    replay: Res<Replay>,
+    tick: Res<SimulationTick>,
+    origin: Res<FloatingOrigin>,
+    ships: SummaryShips,
+    projectiles: SummaryProjectiles,
+    mut exit: EventWriter<AppExit>,
+) {
+    let end = replay.records.last().map(|record| record.tick).unwrap_or(0);
+    if tick.0 < end {
+        return;
+    }
+
+    let summary = ReplaySummary::new(*tick, &origin, &ships, &projectiles);
+    println!("Replay finished after {} ticks", summary.ticks);
+    for (name, position, velocity) in &summary.ships {
+        println!("{name}: position {position} velocity {velocity}");
+    }
+    println!("Projectiles: {}", summary.projectiles.len());
+    commands.insert_resource(summary);
+    exit.send(AppExit::Success);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{
+        controls::Action,
+        fire_control::{FireControl, FireMode},
+        weapon::{tests::weapon_app, Weapon, WeaponFireSet},
+        Player,
+    };
+
+    /// Index of the last tick of the session
+    const LAST_TICK: u32 = 63;
+
+    /// Accelerates for half a second and keeps the trigger pulled after that
+    fn scripted_input(tick: Res<SimulationTick>, mut input: ResMut<TickInput>) {
+        let mut actions = Vec::new();
+        if tick.0 < 32 {
+            actions.push(Action::Accelerate);
+        }
+        if tick.0 >= 8 {
+            actions.push(Action::PrimaryFire);
+        }
+        *input = TickInput::new(actions, None);
+    }
+
+    /// Headless game with the player ship armed with an autocannon
+    fn session_app(configure: impl FnOnce(&mut App)) -> App {
+        let mut app = weapon_app();
+        configure(&mut app);
+        app.init_resource::<FloatingOrigin>().add_systems(
+            FixedUpdate,
+            (
+                crate::player_controller.before(PhysicsSet::SyncBackend),
+                crate::weapon_fire.before(WeaponFireSet),
+            ),
+        );
+        app.world_mut()
+            .spawn((
+                Player,
+                Name::new("Ship"),
+                Transform::default(),
+                RigidBody::Dynamic,
+                Collider::ball(1.0),
+                ExternalForce::default(),
+                ExternalImpulse::default(),
+                Velocity::default(),
+                FireControl::new(FireMode::Linked, FireMode::Linked),
+            ))
+            .with_child((
+                Transform::from_xyz(0.0, 0.0, -2.0),
+                Weapon::new("autocannon"),
+            ));
+        app
+    }
+
+    /// Runs the scripted session, recording it into the `path`
+    fn record(path: &Path) -> ReplaySummary {
+        let mut app = session_app(|app| {
+            app.add_plugins(RecordPlugin(path.to_path_buf()));
+        });
+        app.add_systems(FixedPreUpdate, scripted_input)
+            .add_systems(FixedPostUpdate, summarize_last_tick);
+        // The header is written on startup, which has already passed in the headless app
+        app.world_mut().run_system_once(write_header).unwrap();
+        let summary = run_session(&mut app);
+        app.world_mut().run_system_once(finish_recording).unwrap();
+        summary
+    }
+
+    fn summarize_last_tick(
+        mut commands: Commands,
+        tick: Res<SimulationTick>,
+        origin: Res<FloatingOrigin>,
+        ships: SummaryShips,
+        projectiles: SummaryProjectiles,
+    ) {
+        if tick.0 == LAST_TICK {
+            let summary = ReplaySummary::new(*tick, &origin, &ships, &projectiles);
+            commands.insert_resource(summary);
+        }
+    }
+
+    fn replay(path: &Path) -> ReplaySummary {
+        let mut app = session_app(|app| {
+            app.add_plugins(ReplayPlugin::load(path));
+        });
+        run_session(&mut app)
+    }
+
+    fn run_session(app: &mut App) -> ReplaySummary {
+        for _ in 0..200 {
+            app.update();
+            if let Some(summary) = app.world_mut().remove_resource::<ReplaySummary>() {
+                return summary;
+            }
+        }
+        panic!("Session didn't finish");
+    }
+
+    #[test]
+    fn replay_reproduces_final_state() {
+        let path = std::env::temp_dir().join(format!(
+            "outer-frontiers-{}-session.ofrp",
+            std::process::id()
+        ));
+        let recorded = record(&path);
+        let replayed = replay(&path);
+        let again = replay(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(recorded.ticks, LAST_TICK + 1);
+        let [(name, position, _)] = recorded.ships.as_slice() else {
+            panic!("Expected a single ship, got {:?}", recorded.ships);
+        };
+        assert_eq!(name, "Ship");
+        assert!(position.z < -1.0, "{position}");
+        // Spread of the autocannon draws from the simulation RNG, so shots depend on the seed too
+        assert!(
+            recorded.projectiles.len() >= 2,
+            "{:?}",
+            recorded.projectiles
+        );
+
+        assert_eq!(replayed, recorded);
+        assert_eq!(again, replayed);
+    }
+}
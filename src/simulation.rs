@@ -0,0 +1,244 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaCha8Rng,
+};
+
+use crate::{controls::Action, GameStates};
+
+/// Default number of simulation ticks per second
+pub(crate) const DEFAULT_TICK_RATE: u32 = 64;
+
+/// Runs gameplay and physics in `FixedUpdate` with a constant timestep, so the simulation
+/// doesn't depend on the frame rate and can be reproduced from the recorded [`TickInput`].
+/// Rendering is decoupled from ticks via [`VisualInterpolation`].
+pub(crate) struct SimulationPlugin {
+    /// Number of simulation ticks per second
+    pub(crate) tick_rate: u32,
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        let dt = TickRate(self.tick_rate).duration();
+        app.insert_resource(TickRate(self.tick_rate))
+            .insert_resource(Time::<Fixed>::from_duration(dt))
+            // Should be set before rapier plugin is added, otherwise it complains about Variable timestep.
+            // Rapier is stepped exactly once per tick, so physics and gameplay always see the same time.
+            .insert_resource(TimestepMode::Fixed {
+                dt: dt.as_secs_f32(),
+                substeps: 1,
+            })
+            .add_plugins(RapierPhysicsPlugin::<NoUserData>::default().in_fixed_schedule())
+            .init_resource::<TickInput>()
+            .init_resource::<SimulationTick>()
+            // Replay inserts its own seed before this plugin is built
+            .init_resource::<SimulationRng>()
+            // Gameplay systems read `GlobalTransform` (e.g. weapon barrels), so it's propagated
+            // from the simulated state instead of the interpolated one from the last frame
+            .add_systems(
+                FixedFirst,
+                (
+                    restore_simulated_transforms,
+                    bevy::transform::systems::sync_simple_transforms,
+                    bevy::transform::systems::propagate_transforms,
+                )
+                    .chain(),
+            )
+            .add_systems(FixedLast, count_ticks.run_if(in_state(GameStates::Next)))
+            .add_systems(FixedLast, store_simulated_transforms)
+            .add_systems(
+                RunFixedMainLoop,
+                interpolate_transforms.in_set(RunFixedMainLoopSystem::AfterFixedMainLoop),
+            );
+    }
+}
+
+/// Headless app running the simulation of an already loaded game, advanced by one tick per update.
+/// Gravity is turned off as in the game scene.
+#[cfg(test)]
+pub(crate) fn test_app() -> App {
+    use bevy::time::TimeUpdateStrategy;
+
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        bevy::state::app::StatesPlugin,
+        TransformPlugin,
+        HierarchyPlugin,
+    ))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(
+        TickRate(DEFAULT_TICK_RATE).duration(),
+    ))
+    .add_plugins(SimulationPlugin {
+        tick_rate: DEFAULT_TICK_RATE,
+    })
+    .insert_resource(SimulationRng::new(0))
+    .insert_state(GameStates::Next);
+    // Spawns the rapier context
+    app.update();
+    let world = app.world_mut();
+    world
+        .query::<&mut RapierConfiguration>()
+        .single_mut(world)
+        .gravity = Vec3::ZERO;
+    app
+}
+
+/// Number of simulation ticks per second
+#[derive(Resource, Clone, Copy, Debug)]
+pub(crate) struct TickRate(pub(crate) u32);
+
+impl TickRate {
+    /// Duration of a single simulation tick
+    pub(crate) fn duration(self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.0 as f64)
+    }
+}
+
+/// Index of the current simulation tick, counted from the moment the game scene is set up
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub(crate) struct SimulationTick(pub(crate) u32);
+
+fn count_ticks(mut tick: ResMut<SimulationTick>) {
+    tick.0 += 1;
+}
+
+/// Player input consumed by the simulation during a single tick.
+/// Sampled from the input devices or provided by the replay.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Debug)]
+pub(crate) struct TickInput {
+    /// Bit set of active [`Action`]s, indexed by the action discriminant
+    pub(crate) actions: u32,
+    /// Cursor offset from the window center in logical pixels, if cursor is inside the window
+    pub(crate) cursor_offset: Option<Vec2>,
+}
+
+impl TickInput {
+    pub(crate) fn new(
+        actions: impl IntoIterator<Item = Action>,
+        cursor_offset: Option<Vec2>,
+    ) -> Self {
+        Self {
+            actions: actions
+                .into_iter()
+                .fold(0, |bits, action| bits | 1 << action as u32),
+            cursor_offset,
+        }
+    }
+
+    pub(crate) fn active(&self, action: Action) -> bool {
+        self.actions & (1 << action as u32) != 0
+    }
+}
+
+/// The only source of randomness for the simulation, so it can be reproduced from the seed
+#[derive(Resource)]
+pub(crate) struct SimulationRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl SimulationRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub(crate) fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Uniformly distributed number in `[0, 1)`
+    pub(crate) fn next_f32(&mut self) -> f32 {
+        // 24 bits fit the f32 mantissa exactly
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+impl Default for SimulationRng {
+    /// Random seed for a new session. `RandomState` is used as it is seeded by the OS
+    /// and, unlike `SystemTime`, doesn't panic on wasm.
+    fn default() -> Self {
+        use std::hash::{BuildHasher, Hasher};
+        Self::new(
+            std::collections::hash_map::RandomState::new()
+                .build_hasher()
+                .finish(),
+        )
+    }
+}
+
+impl RngCore for SimulationRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_chacha::rand_core::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+/// Renders entity at the position interpolated between the last two simulation ticks,
+/// so movement stays smooth when frame rate is higher than the tick rate.
+/// Adds one tick of visual latency.
+///
+/// `Transform` of such entities is interpolated only between ticks and restored
+/// to the simulated state at the beginning of each tick, so it should be changed
+/// only by the simulation systems in the fixed schedules.
+#[derive(Component, Default)]
+pub(crate) struct VisualInterpolation {
+    /// Simulated transforms after the previous and the last tick
+    previous: Option<Transform>,
+    last: Option<Transform>,
+}
+
+impl VisualInterpolation {
+    /// Moves stored transforms along with the entity, e.g. when the world origin is moved
+    pub(crate) fn shift(&mut self, delta: Vec3) {
+        for transform in [&mut self.previous, &mut self.last].into_iter().flatten() {
+            transform.translation += delta;
+        }
+    }
+}
+
+fn restore_simulated_transforms(mut query: Query<(&mut Transform, &VisualInterpolation)>) {
+    for (mut transform, interpolation) in query.iter_mut() {
+        if let Some(last) = interpolation.last {
+            // Only skips the write when the transform wasn't interpolated away from the simulated one.
+            // Otherwise rapier sees the restore as a change and syncs the body to the same simulated pose.
+            transform.set_if_neq(last);
+        }
+    }
+}
+
+fn store_simulated_transforms(mut query: Query<(&Transform, &mut VisualInterpolation)>) {
+    for (transform, mut interpolation) in query.iter_mut() {
+        interpolation.previous = interpolation.last.replace(*transform);
+    }
+}
+
+fn interpolate_transforms(
+    time: Res<Time<Fixed>>,
+    mut query: Query<(&mut Transform, &VisualInterpolation)>,
+) {
+    let t = time.overstep_fraction();
+    for (mut transform, interpolation) in query.iter_mut() {
+        if let (Some(previous), Some(last)) = (interpolation.previous, interpolation.last) {
+            transform.translation = previous.translation.lerp(last.translation, t);
+            transform.rotation = previous.rotation.slerp(last.rotation, t);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    camera::CameraRig,
+    controls::{Action, ActionState},
+    GameStates, Player,
+};
+
+/// Maximum angle between the view direction and a ship to select it from the crosshair, in radians
+const CROSSHAIR_ANGLE: f32 = 0.15;
+
+/// Player target selection. Selected target is shown on the HUD and used by the target-lock camera.
+pub(crate) struct TargetingPlugin;
+impl Plugin for TargetingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedTarget>().add_systems(
+            Update,
+            (clear_lost_target, select_target)
+                .chain()
+                .run_if(in_state(GameStates::Next)),
+        );
+    }
+}
+
+/// Marker for ships hostile to the player
+#[derive(Component)]
+pub(crate) struct Hostile;
+
+/// Ship targeted by the player
+#[derive(Resource, Default)]
+pub(crate) struct SelectedTarget(pub(crate) Option<Entity>);
+
+/// Ships that can be targeted, everything except the player itself
+type Targets<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static GlobalTransform,
+        &'static RigidBody,
+        Has<Hostile>,
+    ),
+    Without<Player>,
+>;
+
+fn clear_lost_target(mut target: ResMut<SelectedTarget>, targets: Targets) {
+    if target.0.is_some_and(|entity| !targets.contains(entity)) {
+        target.0 = None;
+    }
+}
+
+fn select_target(
+    actions: Res<ActionState>,
+    mut target: ResMut<SelectedTarget>,
+    targets: Targets,
+    player: Query<&GlobalTransform, With<Player>>,
+    camera: Query<&GlobalTransform, With<CameraRig>>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let player = player.translation();
+    // Closest first, ties are resolved by entity for a stable cycling order
+    let mut candidates: Vec<_> = targets
+        .iter()
+        .filter(|(_, _, body, _)| **body == RigidBody::Dynamic)
+        .map(|(entity, transform, _, hostile)| {
+            let distance = transform.translation().distance(player);
+            (entity, transform.translation(), hostile, distance)
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.3.total_cmp(&b.3).then(a.0.cmp(&b.0)));
+
+    let selected = if actions.just_activated(Action::TargetNearest) {
+        candidates.first().map(|(entity, ..)| *entity)
+    } else if actions.just_activated(Action::TargetCrosshair) {
+        let Ok(camera) = camera.get_single() else {
+            return;
+        };
+        candidates
+            .iter()
+            .map(|(entity, position, ..)| {
+                let angle = camera
+                    .forward()
+                    .angle_between(*position - camera.translation());
+                (*entity, angle)
+            })
+            .filter(|(_, angle)| *angle < CROSSHAIR_ANGLE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(entity, _)| entity)
+    } else if actions.just_activated(Action::NextHostileTarget) {
+        let hostiles: Vec<_> = candidates
+            .iter()
+            .filter(|(.., hostile, _)| *hostile)
+            .map(|(entity, ..)| *entity)
+            .collect();
+        let current = hostiles.iter().position(|e| Some(*e) == target.0);
+        let next = current.map_or(0, |i| (i + 1) % hostiles.len().max(1));
+        hostiles.get(next).copied()
+    } else {
+        return;
+    };
+
+    if selected.is_some() && selected != target.0 {
+        target.0 = selected;
+        info!("Target: {:?}", target.0);
+    }
+}
+
+/// Time after which a projectile fired from the shooter with `speed` relative to it hits the target.
+/// `relative_position` and `relative_velocity` are of the target relative to the shooter,
+/// the target is assumed to keep its velocity.
+///
+/// Returns the earliest non-negative time, or `None` if the projectile can never reach the target,
+/// e.g. when the target is moving away faster than the projectile.
+pub(crate) fn intercept_time(
+    relative_position: Vec3,
+    relative_velocity: Vec3,
+    speed: f32,
+) -> Option<f32> {
+    // Solve |p + v * t| = speed * t for t >= 0:
+    // (v·v - speed²) t² + 2 (p·v) t + p·p = 0
+    let a = relative_velocity.length_squared() - speed * speed;
+    let b = 2.0 * relative_position.dot(relative_velocity);
+    let c = relative_position.length_squared();
+    if c == 0.0 {
+        return Some(0.0);
+    }
+
+    if a.abs() < 1e-6 {
+        // Projectile and target speeds are equal, the equation is linear
+        let t = -c / b;
+        return (b < 0.0).then_some(t);
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let t1 = (-b - root) / (2.0 * a);
+    let t2 = (-b + root) / (2.0 * a);
+    [t1, t2]
+        .into_iter()
+        .filter(|t| *t >= 0.0)
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+/// World position to aim at, so a projectile hits the target. The projectile inherits
+/// the shooter velocity, as in [`crate::weapon::Weapon`] firing, so only the relative motion matters.
+pub(crate) fn lead_position(
+    shooter_position: Vec3,
+    shooter_velocity: Vec3,
+    target_position: Vec3,
+    target_velocity: Vec3,
+    projectile_speed: f32,
+) -> Option<Vec3> {
+    let relative_velocity = target_velocity - shooter_velocity;
+    let t = intercept_time(
+        target_position - shooter_position,
+        relative_velocity,
+        projectile_speed,
+    )?;
+    Some(target_position + relative_velocity * t)
+}
+
+/// Rate at which the distance to the target decreases, negative if the target is moving away
+pub(crate) fn closing_speed(relative_position: Vec3, relative_velocity: Vec3) -> f32 {
+    -relative_velocity.dot(relative_position.normalize_or_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stationary_target_is_aimed_directly() {
+        let target = Vec3::new(30.0, 40.0, 0.0);
+        let time = intercept_time(target, Vec3::ZERO, 10.0).unwrap();
+        assert!((time - 5.0).abs() < 1e-4, "{time}");
+        let aim = lead_position(Vec3::ZERO, Vec3::ZERO, target, Vec3::ZERO, 10.0).unwrap();
+        assert!(aim.distance(target) < 1e-4, "{aim}");
+    }
+
+    #[test]
+    fn crossing_target_is_led() {
+        let target = Vec3::new(0.0, 0.0, -100.0);
+        let velocity = Vec3::new(50.0, 0.0, 0.0);
+        let time = intercept_time(target, velocity, 100.0).unwrap();
+        // (50 t)² + 100² = (100 t)²
+        let expected = (100.0f32 * 100.0 / 7500.0).sqrt();
+        assert!((time - expected).abs() < 1e-4, "{time} != {expected}");
+
+        let aim = lead_position(Vec3::ZERO, Vec3::ZERO, target, velocity, 100.0).unwrap();
+        assert!(aim.distance(target + velocity * expected) < 1e-3, "{aim}");
+        // Projectile flying at the aim point arrives there together with the target
+        assert!((aim.length() / 100.0 - time).abs() < 1e-4);
+    }
+
+    #[test]
+    fn shooter_velocity_is_inherited_by_projectile() {
+        // Flying side by side, so the target is stationary relative to the shooter
+        let velocity = Vec3::new(0.0, 20.0, 0.0);
+        let target = Vec3::new(0.0, 0.0, -50.0);
+        let aim = lead_position(Vec3::ZERO, velocity, target, velocity, 100.0).unwrap();
+        assert!(aim.distance(target) < 1e-4, "{aim}");
+    }
+
+    #[test]
+    fn target_receding_faster_than_projectile_is_unreachable() {
+        let target = Vec3::new(0.0, 0.0, -100.0);
+        assert_eq!(
+            intercept_time(target, Vec3::new(0.0, 0.0, -150.0), 100.0),
+            None
+        );
+        assert_eq!(
+            lead_position(
+                Vec3::ZERO,
+                Vec3::ZERO,
+                target,
+                Vec3::new(0.0, 0.0, -150.0),
+                100.0
+            ),
+            None
+        );
+        // Receding with the projectile speed, it never catches up either
+        assert_eq!(
+            intercept_time(target, Vec3::new(0.0, 0.0, -100.0), 100.0),
+            None
+        );
+    }
+
+    #[test]
+    fn closing_speed_sign() {
+        let target = Vec3::new(0.0, 0.0, -100.0);
+        assert_eq!(closing_speed(target, Vec3::new(0.0, 0.0, 10.0)), 10.0);
+        assert_eq!(closing_speed(target, Vec3::new(0.0, 0.0, -10.0)), -10.0);
+    }
+}
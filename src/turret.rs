@@ -0,0 +1,536 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    faction::{Faction, FriendlyFire},
+    projectile::HitVolume,
+    targeting::lead_position,
+    weapon::{weapon_shooter, ProjectileOwner, ShipMotion, Weapon, WeaponFireSet, WeaponTypes},
+    GameStates,
+};
+
+/// Maximum aiming error at which turrets open fire, in radians
+const FIRE_TOLERANCE: f32 = 0.03;
+
+/// Turrets rotate their joints towards the lead position of the nearest ship of another [`Faction`]
+/// and fire their weapons once aimed, turrets of neutral ships don't engage ships.
+/// Point defense turrets engage incoming projectiles with a [`HitVolume`] instead,
+/// the one to hit the ship first is engaged.
+///
+/// A turret is a [`Turret`] yaw joint (`turret_base.` model node) rotating around its Y axis
+/// and a [`TurretGun`] pitch joint (`turret_gun.` node below it) rotating around its X axis.
+/// Weapons on the gun or below it are fired by the turret.
+pub(crate) struct TurretPlugin;
+impl Plugin for TurretPlugin {
+    fn build(&self, app: &mut App) {
+        // Joints are rotated after the tick's transform propagation,
+        // barrels are propagated again so weapons fire along the new aim
+        app.add_systems(
+            FixedUpdate,
+            (aim_turrets, bevy::transform::systems::propagate_transforms)
+                .chain()
+                .before(WeaponFireSet)
+                .before(PhysicsSet::SyncBackend)
+                .run_if(in_state(GameStates::Next)),
+        );
+    }
+}
+
+/// Angle limits in radians, relative to the joint rest rotation
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Arc {
+    pub(crate) min: f32,
+    pub(crate) max: f32,
+}
+
+/// Yaw joint of a turret
+#[derive(Component, Clone, Debug)]
+pub(crate) struct Turret {
+    /// Joint speed in radians per second, shared by yaw and pitch
+    traverse_speed: f32,
+    /// Maximum distance to a target
+    range: f32,
+    /// Full circle if not set
+    yaw_arc: Option<Arc>,
+    pitch_arc: Arc,
+    /// Projectiles passing closer than this distance to the ship are engaged instead of ships
+    point_defense: Option<f32>,
+    yaw: f32,
+    /// Model rotation of the joint, captured on the first tick
+    rest: Option<Quat>,
+}
+
+impl Turret {
+    pub(crate) fn new(traverse_speed: f32, range: f32) -> Self {
+        Self {
+            traverse_speed,
+            range,
+            yaw_arc: None,
+            pitch_arc: Arc {
+                min: -0.1,
+                max: PI / 2.0,
+            },
+            point_defense: None,
+            yaw: 0.0,
+            rest: None,
+        }
+    }
+
+    pub(crate) fn with_yaw_arc(mut self, min: f32, max: f32) -> Self {
+        self.yaw_arc = Some(Arc { min, max });
+        self
+    }
+
+    pub(crate) fn with_pitch_arc(mut self, min: f32, max: f32) -> Self {
+        self.pitch_arc = Arc { min, max };
+        self
+    }
+
+    /// Engage projectiles that would pass within the `threat_radius` from the ship
+    pub(crate) fn with_point_defense(mut self, threat_radius: f32) -> Self {
+        self.point_defense = Some(threat_radius);
+        self
+    }
+}
+
+/// Pitch joint of a turret, a descendant of the [`Turret`]
+#[derive(Component, Clone, Default, Debug)]
+pub(crate) struct TurretGun {
+    pitch: f32,
+    rest: Option<Quat>,
+}
+
+/// Angle wrapped to `[-PI, PI)`
+pub(crate) fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+/// Yaw around Y and pitch around X that point the -Z axis of a joint along the local `direction`
+pub(crate) fn aim_angles(direction: Vec3) -> (f32, f32) {
+    let yaw = (-direction.x).atan2(-direction.z);
+    let pitch = direction.y.atan2(direction.xz().length());
+    (yaw, pitch)
+}
+
+/// Closest angle within the `arc`, any angle is reachable without limits
+pub(crate) fn clamp_to_arc(angle: f32, arc: Option<Arc>) -> f32 {
+    let angle = wrap_angle(angle);
+    let Some(arc) = arc else {
+        return angle;
+    };
+    if (arc.min..=arc.max).contains(&angle) {
+        return angle;
+    }
+    // Outside of the arc, the nearest limit is chosen by the angular distance
+    if wrap_angle(angle - arc.min).abs() <= wrap_angle(angle - arc.max).abs() {
+        arc.min
+    } else {
+        arc.max
+    }
+}
+
+/// Joint angle after moving from `current` towards `target` by no more than `max_step`.
+/// Without an arc the joint turns the shortest way around, otherwise it stays within the arc.
+pub(crate) fn step_angle(current: f32, target: f32, max_step: f32, arc: Option<Arc>) -> f32 {
+    let delta = match arc {
+        None => wrap_angle(target - current),
+        Some(_) => target - current,
+    };
+    wrap_angle(current + delta.clamp(-max_step, max_step))
+}
+
+/// Seconds until a projectile at `rel_position` with `rel_velocity`, both relative to a ship,
+/// passes closest to it, if it passes within the `radius`
+pub(crate) fn time_to_impact(rel_position: Vec3, rel_velocity: Vec3, radius: f32) -> Option<f32> {
+    let speed_squared = rel_velocity.length_squared();
+    let time = if speed_squared > 0.0 {
+        (-rel_position.dot(rel_velocity) / speed_squared).max(0.0)
+    } else {
+        0.0
+    };
+    let miss = (rel_position + rel_velocity * time).length();
+    (miss <= radius).then_some(time)
+}
+
+/// Rotation of the entity's parent in world space
+fn parent_rotation(
+    entity: Entity,
+    parents: &Query<&Parent>,
+    transforms: &Query<&GlobalTransform>,
+) -> Quat {
+    parents
+        .get(entity)
+        .ok()
+        .and_then(|parent| transforms.get(parent.get()).ok())
+        .map(|transform| transform.to_scale_rotation_translation().1)
+        .unwrap_or_default()
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn aim_turrets(
+    time: Res<Time>,
+    weapon_types: Res<WeaponTypes>,
+    mut bases: Query<(Entity, &mut Turret, &mut Transform), Without<TurretGun>>,
+    mut guns: Query<(&mut TurretGun, &mut Transform), Without<Turret>>,
+    mut weapons: Query<&mut Weapon>,
+    targets: Query<(Entity, &GlobalTransform, &Faction)>,
+    threats: Query<(Entity, &Velocity, &ProjectileOwner), With<HitVolume>>,
+    factions: Query<&Faction>,
+    friendly_fire: Res<FriendlyFire>,
+    transforms: Query<&GlobalTransform>,
+    velocities: Query<&Velocity>,
+    ships: Query<ShipMotion>,
+    parents: Query<&Parent>,
+    children: Query<&Children>,
+) {
+    let dt = time.delta_secs();
+    for (base, mut turret, mut base_transform) in bases.iter_mut() {
+        let Some(gun) = children
+            .iter_descendants(base)
+            .find(|entity| guns.contains(*entity))
+        else {
+            continue;
+        };
+        let Ok(origin) = transforms.get(gun).map(|transform| transform.translation()) else {
+            continue;
+        };
+        let gun_weapons: Vec<_> = std::iter::once(gun)
+            .chain(children.iter_descendants(gun))
+            .filter(|entity| weapons.contains(*entity))
+            .collect();
+        let (ship, ship_velocity) = weapon_shooter(gun, origin, &parents, &ships);
+        let faction = ship.and_then(|ship| factions.get(ship).ok()).copied();
+
+        let target = match turret.point_defense {
+            None => targets
+                .iter()
+                .filter(|(_, _, target_faction)| faction.is_some_and(|f| f != **target_faction))
+                .map(|(target, transform, _)| (target, transform.translation()))
+                .filter(|(_, position)| position.distance(origin) <= turret.range)
+                .min_by(|a, b| {
+                    a.1.distance_squared(origin)
+                        .total_cmp(&b.1.distance_squared(origin))
+                        .then(a.0.cmp(&b.0))
+                }),
+            Some(threat_radius) => {
+                let center = ship
+                    .and_then(|ship| transforms.get(ship).ok())
+                    .map_or(origin, |transform| transform.translation());
+                threats
+                    .iter()
+                    // Projectiles that can't hit the ship are no threat to it
+                    .filter(|(_, _, owner)| {
+                        ship.is_none_or(|ship| owner.can_hit(ship, faction, *friendly_fire))
+                    })
+                    .filter_map(|(threat, velocity, _)| {
+                        let position = transforms.get(threat).ok()?.translation();
+                        if position.distance(origin) > turret.range {
+                            return None;
+                        }
+                        let time = time_to_impact(
+                            position - center,
+                            velocity.linvel - ship_velocity,
+                            threat_radius,
+                        )?;
+                        Some((threat, position, time))
+                    })
+                    .min_by(|a, b| a.2.total_cmp(&b.2).then(a.0.cmp(&b.0)))
+                    .map(|(threat, position, _)| (threat, position))
+            }
+        };
+        let Some((target, target_position)) = target else {
+            continue;
+        };
+        // Beams hit instantly, so they are aimed directly at the target
+        let projectile_speed = gun_weapons
+            .first()
+            .and_then(|weapon| weapons.get(*weapon).ok())
+            .and_then(|weapon| weapon_types.get(weapon))
+            .and_then(|weapon_type| weapon_type.projectile_speed());
+        let aim = projectile_speed
+            .and_then(|speed| {
+                let target_velocity = velocities.get(target).map(|v| v.linvel).unwrap_or_default();
+                lead_position(
+                    origin,
+                    ship_velocity,
+                    target_position,
+                    target_velocity,
+                    speed,
+                )
+            })
+            .unwrap_or(target_position);
+
+        let rest = *turret.rest.get_or_insert(base_transform.rotation);
+        let frame = parent_rotation(base, &parents, &transforms) * rest;
+        let (desired_yaw, desired_pitch) = aim_angles(frame.inverse() * (aim - origin));
+        let max_step = turret.traverse_speed * dt;
+
+        let yaw_target = clamp_to_arc(desired_yaw, turret.yaw_arc);
+        turret.yaw = step_angle(turret.yaw, yaw_target, max_step, turret.yaw_arc);
+        base_transform.rotation = rest * Quat::from_rotation_y(turret.yaw);
+
+        let (mut turret_gun, mut gun_transform) = guns.get_mut(gun).unwrap();
+        let gun_rest = *turret_gun.rest.get_or_insert(gun_transform.rotation);
+        let pitch_target = clamp_to_arc(desired_pitch, Some(turret.pitch_arc));
+        turret_gun.pitch = step_angle(
+            turret_gun.pitch,
+            pitch_target,
+            max_step,
+            Some(turret.pitch_arc),
+        );
+        gun_transform.rotation = gun_rest * Quat::from_rotation_x(turret_gun.pitch);
+
+        let is_aimed = wrap_angle(desired_yaw - turret.yaw).abs() < FIRE_TOLERANCE
+            && (desired_pitch - turret_gun.pitch).abs() < FIRE_TOLERANCE;
+        if is_aimed {
+            for entity in gun_weapons {
+                weapons.get_mut(entity).unwrap().fire();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        projectile::Bullet,
+        simulation::DEFAULT_TICK_RATE,
+        targeting::Hostile,
+        weapon::tests::{run, ship, target, weapon_app, RecordedHits},
+        weapon::Projectile,
+    };
+
+    const FRONT_ARC: Arc = Arc {
+        min: -PI / 2.0,
+        max: PI / 2.0,
+    };
+
+    fn assert_angle(angle: f32, expected: f32) {
+        assert!((angle - expected).abs() < 1e-5, "{angle} != {expected}");
+    }
+
+    #[test]
+    fn angle_is_clamped_to_nearest_arc_limit() {
+        assert_angle(clamp_to_arc(0.3, Some(FRONT_ARC)), 0.3);
+        assert_angle(clamp_to_arc(0.6 * PI, Some(FRONT_ARC)), FRONT_ARC.max);
+        assert_angle(clamp_to_arc(-0.6 * PI, Some(FRONT_ARC)), FRONT_ARC.min);
+        assert_angle(clamp_to_arc(0.9 * PI, Some(FRONT_ARC)), FRONT_ARC.max);
+        assert_angle(clamp_to_arc(3.0 * PI / 2.0, None), -PI / 2.0);
+    }
+
+    #[test]
+    fn joint_stays_within_arc() {
+        // Turning the shortest way around from 0.4 * PI to -0.4 * PI would cross the rear
+        let yaw = step_angle(0.4 * PI, -0.4 * PI, 0.5, Some(FRONT_ARC));
+        assert_angle(yaw, 0.4 * PI - 0.5);
+        let yaw = step_angle(0.9 * PI, -0.9 * PI, 1.0, None);
+        assert_angle(yaw, -0.9 * PI);
+    }
+
+    #[test]
+    fn time_to_impact_of_approaching_threats() {
+        let head_on = time_to_impact(Vec3::new(0.0, 0.0, -100.0), Vec3::new(0.0, 0.0, 50.0), 5.0);
+        assert_eq!(head_on, Some(2.0));
+        // Passes 20 units beside the ship
+        let miss = time_to_impact(Vec3::new(20.0, 0.0, -100.0), Vec3::new(0.0, 0.0, 50.0), 5.0);
+        assert_eq!(miss, None);
+        let receding = time_to_impact(Vec3::new(0.0, 0.0, -100.0), Vec3::new(0.0, 0.0, -50.0), 5.0);
+        assert_eq!(receding, None);
+        // Already within the radius and moving away, so it's about to hit
+        let inside = time_to_impact(Vec3::new(0.0, 0.0, -3.0), Vec3::new(0.0, 0.0, -50.0), 5.0);
+        assert_eq!(inside, Some(0.0));
+    }
+
+    /// Heavy Alliance ship with a turret mounted on top, so the recoil barely moves it.
+    /// The turret gun faces forward at rest.
+    fn turret_ship(app: &mut App, turret: Turret, weapon: &str) {
+        app.world_mut()
+            .spawn(ship(Vec3::ZERO))
+            .insert((
+                ColliderMassProperties::Density(1000.0),
+                Faction::Alliance.bundle(),
+            ))
+            .with_children(|ship| {
+                ship.spawn((Transform::from_xyz(0.0, 3.0, 0.0), turret))
+                    .with_child((
+                        Transform::default(),
+                        TurretGun::default(),
+                        Weapon::new(weapon),
+                    ));
+            });
+    }
+
+    #[test]
+    fn turret_turns_to_and_hits_target() {
+        let mut app = weapon_app();
+        app.add_plugins(TurretPlugin);
+        turret_ship(&mut app, Turret::new(1.5, 600.0), "autocannon");
+        // Behind and above the turret, so both joints have to turn
+        let target = app
+            .world_mut()
+            .spawn((
+                target(Vec3::new(40.0, 30.0, 40.0)),
+                Faction::Raiders.bundle(),
+            ))
+            .id();
+
+        run(&mut app, 5.0);
+
+        let world = app.world_mut();
+        let gun = *world
+            .query_filtered::<&GlobalTransform, With<TurretGun>>()
+            .single(world);
+        let target_position = world.get::<Transform>(target).unwrap().translation;
+        let error = gun
+            .forward()
+            .angle_between(target_position - gun.translation());
+        assert!(error < 0.05, "{error}");
+        let hits = &world.resource::<RecordedHits>().0;
+        assert!(!hits.is_empty());
+        assert!(hits.iter().all(|hit| hit.target == target));
+    }
+
+    #[test]
+    fn turret_does_not_fire_outside_of_arc() {
+        let mut app = weapon_app();
+        app.add_plugins(TurretPlugin);
+        turret_ship(
+            &mut app,
+            Turret::new(1.5, 600.0).with_yaw_arc(-0.4 * PI, 0.4 * PI),
+            "autocannon",
+        );
+        app.world_mut()
+            .spawn((target(Vec3::new(0.0, 3.0, 50.0)), Faction::Raiders.bundle()));
+
+        run(&mut app, 5.0);
+
+        let world = app.world_mut();
+        let turret = world.query::<&Turret>().single(world);
+        assert_angle(turret.yaw.abs(), 0.4 * PI);
+        assert!(world.resource::<RecordedHits>().0.is_empty());
+    }
+
+    #[test]
+    fn turret_ignores_ships_of_own_faction() {
+        let mut app = weapon_app();
+        app.add_plugins(TurretPlugin);
+        turret_ship(&mut app, Turret::new(1.5, 600.0), "autocannon");
+        // Hostile to the player, but an ally of the turret
+        app.world_mut().spawn((
+            target(Vec3::new(0.0, 3.0, -50.0)),
+            Faction::Alliance.bundle(),
+            Hostile,
+        ));
+        // Neutral objects like stations are not engaged either
+        app.world_mut().spawn(target(Vec3::new(0.0, 3.0, 50.0)));
+
+        run(&mut app, 3.0);
+
+        let world = app.world_mut();
+        let turret = world.query::<&Turret>().single(world);
+        assert_eq!(turret.yaw, 0.0);
+        assert!(world.resource::<RecordedHits>().0.is_empty());
+    }
+
+    #[test]
+    fn turret_fires_along_current_aim() {
+        let mut app = weapon_app();
+        app.add_plugins(TurretPlugin);
+        turret_ship(&mut app, Turret::new(3.0, 600.0), "point_defense");
+        // Crosses in front of the turret at about 1 rad/s, so the turret keeps turning while firing
+        app.world_mut()
+            .spawn((
+                target(Vec3::new(-40.0, 3.0, -40.0)),
+                Faction::Raiders.bundle(),
+            ))
+            .insert((
+                RigidBody::KinematicVelocityBased,
+                Velocity::linear(Vec3::new(50.0, 0.0, 0.0)),
+            ));
+
+        let mut seen = Vec::new();
+        for _ in 0..(DEFAULT_TICK_RATE * 2) {
+            app.update();
+            let world = app.world_mut();
+            let gun = *world
+                .query_filtered::<&GlobalTransform, With<TurretGun>>()
+                .single(world);
+            let fired: Vec<_> = world
+                .query::<(Entity, &Bullet, &Transform)>()
+                .iter(world)
+                .filter(|(entity, bullet, _)| bullet.is_live() && !seen.contains(entity))
+                .map(|(entity, _, transform)| (entity, transform.rotation * Vec3::Y))
+                .collect();
+            for (bullet, direction) in fired {
+                seen.push(bullet);
+                // Within the point defense spread of 0.2 degrees, a tick behind would be off by 2.7
+                let error = direction.angle_between(gun.forward().into());
+                assert!(error < 0.004, "{error}");
+            }
+        }
+        assert!(seen.len() >= 3, "{}", seen.len());
+    }
+
+    /// Raider projectile with a hit volume, moved by rapier like a missile
+    fn threat(position: Vec3, velocity: Vec3) -> impl Bundle {
+        (
+            Transform::from_translation(position),
+            RigidBody::KinematicVelocityBased,
+            Velocity::linear(velocity),
+            HitVolume { radius: 1.0 },
+            ProjectileOwner {
+                ship: None,
+                faction: Some(Faction::Raiders),
+            },
+            Projectile,
+        )
+    }
+
+    #[test]
+    fn point_defense_shoots_down_first_threat_to_hit() {
+        let mut app = weapon_app();
+        app.add_plugins(TurretPlugin);
+        turret_ship(
+            &mut app,
+            Turret::new(3.0, 600.0).with_point_defense(5.0),
+            "point_defense",
+        );
+        let world = app.world_mut();
+        // Nearer one is slow and hits in about 4 seconds, the farther one in 2
+        let slow = world
+            .spawn(threat(
+                Vec3::new(60.0, 0.0, -60.0),
+                Vec3::new(-14.0, 0.0, 14.0),
+            ))
+            .id();
+        let fast = world
+            .spawn(threat(
+                Vec3::new(0.0, 0.0, -200.0),
+                Vec3::new(0.0, 0.0, 100.0),
+            ))
+            .id();
+        // Passes far from the ship, so it's ignored
+        world.spawn(threat(
+            Vec3::new(30.0, 0.0, -20.0),
+            Vec3::new(0.0, 0.0, 100.0),
+        ));
+
+        let ticks = (0..DEFAULT_TICK_RATE * 2)
+            .find(|_| {
+                app.update();
+                app.world().get_entity(fast).is_err()
+            })
+            .expect("fast threat reached the ship");
+
+        let world = app.world();
+        let hits = &world.resource::<RecordedHits>().0;
+        assert_eq!(hits.first().map(|hit| hit.target), Some(fast));
+        assert!(world.get_entity(slow).is_ok());
+        // Shot down well before it could hit, while still far from the ship
+        let seconds = ticks as f32 / DEFAULT_TICK_RATE as f32;
+        assert!(seconds < 1.8, "{seconds}");
+    }
+}
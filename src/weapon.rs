@@ -1,172 +1,1787 @@
+use std::collections::BTreeMap;
+
 use bevy::{
     pbr::{NotShadowCaster, NotShadowReceiver},
     prelude::*,
+    utils::HashMap,
 };
 
 use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
 
-use crate::GameStates;
+use crate::{
+    assets::Catalogs,
+    beam::{fire_beams, BeamSpec, BeamType},
+    explosion::{Explosion, Explosive},
+    faction::{Faction, FriendlyFire, PROJECTILE_GROUP},
+    fire_control::{fire_weapon_groups, WeaponGroup},
+    hull::{Hull, HullDamageSet, HullDestroyed, HullHit},
+    missile::{
+        detect_proximity_hits, fire_missiles, guide_missiles, seeker_candidate, LauncherSpec,
+        LauncherType, SeekerLock,
+    },
+    power::PowerPool,
+    projectile::{advance_projectiles, Bullet, HitDetection, ProjectilePool},
+    simulation::{SimulationRng, VisualInterpolation},
+    GameStates,
+};
 
 pub(crate) struct WeaponPlugin;
 impl Plugin for WeaponPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameStates::Next), setup_projectile)
-            .add_systems(Update, weapon_fire.run_if(in_state(GameStates::Next)))
-            // Run `lifetime` in PostUpdate so it can despawn entities after all collisions are resolved
-            .add_systems(PostUpdate, lifetime);
+        app.add_event::<ProjectileHit>()
+            .init_resource::<ProjectilePool>()
+            .init_resource::<FriendlyFire>()
+            .add_systems(OnEnter(GameStates::Next), setup_weapon_types)
+            .add_systems(
+                FixedUpdate,
+                (
+                    fire_weapon_groups,
+                    (weapon_fire, fire_beams.in_set(HullDamageSet), fire_missiles),
+                )
+                    .chain()
+                    .in_set(WeaponFireSet)
+                    .before(PhysicsSet::SyncBackend)
+                    .run_if(in_state(GameStates::Next)),
+            )
+            .add_systems(
+                FixedUpdate,
+                guide_missiles
+                    .after(WeaponFireSet)
+                    .before(PhysicsSet::SyncBackend)
+                    .run_if(in_state(GameStates::Next)),
+            )
+            // Run `lifetime` after physics step so it can despawn entities after all collisions are resolved
+            .add_systems(
+                FixedUpdate,
+                (
+                    (
+                        detect_sensor_hits,
+                        advance_projectiles,
+                        detect_proximity_hits,
+                        apply_projectile_hits.in_set(HullDamageSet),
+                        hit_effects,
+                    )
+                        .chain()
+                        .run_if(in_state(GameStates::Next)),
+                    lifetime,
+                )
+                    .chain()
+                    .after(PhysicsSet::Writeback),
+            );
     }
 }
 
-/// Entity lifetime in seconds, after which entity should be destroyed
+/// Set with the systems that fire weapons. Systems calling [`crate::fire_control::FireControl::fire`]
+/// or [`Weapon::fire`] should run before it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct WeaponFireSet;
+
+/// Entity lifetime in seconds, after which entity should be destroyed.
+/// [`Explosive`] entities self-destruct, exploding where they are.
 #[derive(Component, Clone)]
-struct Lifetime(f32);
+pub(crate) struct Lifetime(pub(crate) f32);
 
-fn lifetime(mut commands: Commands, time: Res<Time>, mut query: Query<(Entity, &mut Lifetime)>) {
-    for (entity, mut lifetime) in query.iter_mut() {
+#[allow(clippy::type_complexity)]
+pub(crate) fn lifetime(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(
+        Entity,
+        &mut Lifetime,
+        &Transform,
+        Option<&Explosive>,
+        Option<&ProjectileOwner>,
+    )>,
+    mut explosions: EventWriter<Explosion>,
+) {
+    for (entity, mut lifetime, transform, explosive, owner) in query.iter_mut() {
         lifetime.0 -= time.delta_secs();
         if lifetime.0 <= 0.0 {
-            commands.entity(entity).despawn_recursive();
+            if let Some(explosive) = explosive {
+                explosions.send(Explosion {
+                    center: transform.translation,
+                    spec: explosive.0,
+                    cause: owner.and_then(|owner| owner.ship).unwrap_or(entity),
+                });
+            }
+            // Projectile could be already despawned on hit
+            commands.entity(entity).try_despawn_recursive();
         }
     }
 }
 
-#[derive(Resource)]
-struct Projectile {
-    collider: Collider,
+/// Spawned projectile
+#[derive(Component)]
+pub(crate) struct Projectile;
+
+/// Who fired the projectile, decides what it can hit
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) struct ProjectileOwner {
+    /// Ship that fired the projectile, it can't be hit by its own projectiles
+    pub(crate) ship: Option<Entity>,
+    /// Faction of the ship, projectiles of unaffiliated shooters hit everyone
+    pub(crate) faction: Option<Faction>,
+}
+
+impl ProjectileOwner {
+    /// Owner with the faction of the `ship`, if any
+    pub(crate) fn new(ship: Option<Entity>, factions: &Query<&Faction>) -> Self {
+        Self {
+            ship,
+            faction: ship.and_then(|ship| factions.get(ship).ok()).copied(),
+        }
+    }
+
+    /// Whether projectiles of the owner can hit the `target` ship of the `target_faction`
+    pub(crate) fn can_hit(
+        &self,
+        target: Entity,
+        target_faction: Option<Faction>,
+        friendly_fire: FriendlyFire,
+    ) -> bool {
+        self.ship != Some(target)
+            && (friendly_fire == FriendlyFire::On
+                || self.faction.is_none()
+                || self.faction != target_faction)
+    }
+
+    /// Whether projectiles of the owner can shoot down projectiles of the `other` owner,
+    /// same as for ships of the other owner, except ships never shoot down their own projectiles
+    pub(crate) fn can_hit_projectile(
+        &self,
+        other: &ProjectileOwner,
+        friendly_fire: FriendlyFire,
+    ) -> bool {
+        (self.ship.is_none() || self.ship != other.ship)
+            && (friendly_fire == FriendlyFire::On
+                || self.faction.is_none()
+                || self.faction != other.faction)
+    }
+
+    /// Collision groups of the owner projectiles: they don't hit each other,
+    /// and hit ships of the owner faction only with friendly fire
+    pub(crate) fn collision_groups(&self, friendly_fire: FriendlyFire) -> CollisionGroups {
+        let filters = match (self.faction, friendly_fire) {
+            (Some(faction), FriendlyFire::Off) => Group::ALL - faction.group(),
+            _ => Group::ALL,
+        };
+        CollisionGroups::new(PROJECTILE_GROUP, filters - PROJECTILE_GROUP)
+    }
+
+    /// Ray cast filter for the owner projectiles and beams: solid colliders allowed
+    /// by [`Self::collision_groups`], except the owner ship
+    pub(crate) fn query_filter(&self, friendly_fire: FriendlyFire) -> QueryFilter<'static> {
+        let mut filter = QueryFilter::default()
+            .exclude_sensors()
+            .groups(self.collision_groups(friendly_fire));
+        if let Some(ship) = self.ship {
+            filter = filter.exclude_rigid_body(ship);
+        }
+        filter
+    }
+}
+
+/// Damage dealt on hit
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct Damage(pub(crate) f32);
+
+/// Projectile hit the `target`
+#[derive(Event, Clone, Debug)]
+pub(crate) struct ProjectileHit {
+    /// Projectile entity, despawned or returned to the [`ProjectilePool`] after the hit
+    pub(crate) projectile: Entity,
+    pub(crate) shooter: Option<Entity>,
+    pub(crate) target: Entity,
+    /// Hit position and surface normal of the target in world space
+    pub(crate) point: Vec3,
+    pub(crate) normal: Vec3,
+    pub(crate) damage: f32,
+    /// Projectile velocity and mass at the moment of the hit, the target is pushed by its momentum
+    pub(crate) velocity: Vec3,
+    pub(crate) mass: f32,
+}
+
+/// Weapon catalog asset, see `assets/catalog.weapons.ron`
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub(crate) struct WeaponCatalog {
+    /// Weapon specs by the name referenced by [`Weapon::new`]
+    weapons: BTreeMap<String, WeaponSpec>,
+}
+
+/// Catalog entry of a single weapon
+#[derive(Deserialize, Clone, Debug)]
+enum WeaponSpec {
+    Gun(GunSpec),
+    Beam(BeamSpec),
+    Launcher(LauncherSpec),
+}
+
+/// Catalog entry of a weapon firing projectiles
+#[derive(Deserialize, Clone, Debug)]
+struct GunSpec {
+    /// Shots per second once the weapon is spun up
+    rate_of_fire: f32,
+    /// Seconds to reach the full rate of fire from idle, zero for instant
+    #[serde(default)]
+    spin_up: f32,
+    /// Pause after a number of shots, the weapon fires continuously without it
+    #[serde(default)]
+    burst: Option<Burst>,
+    /// Half-angle of the cone projectiles are randomly spread in, in degrees
+    #[serde(default)]
+    spread: f32,
+    /// Spread growing with sustained fire, added to the base one
+    #[serde(default)]
+    bloom: Option<Bloom>,
+    /// Limited ammo reloaded after the magazine is spent, unlimited without it
+    #[serde(default)]
+    magazine: Option<Magazine>,
+    /// Heat buildup locking the weapon out once it overheats
+    #[serde(default)]
+    heat: Option<Heat>,
+    /// Energy drawn from the ship [`PowerPool`] for each shot
+    #[serde(default)]
+    energy_per_shot: f32,
+    /// Fraction of the projectile momentum pushing the ship back, below 1 for recoil dampers
+    #[serde(default = "default_recoil")]
+    recoil: f32,
+    projectile: ProjectileSpec,
+}
+
+fn default_recoil() -> f32 {
+    1.0
+}
+
+impl GunSpec {
+    /// Rejects values the weapon can't fire with, like a zero rate of fire
+    fn validate(&self) -> Result<(), String> {
+        if self.rate_of_fire <= 0.0 {
+            return Err(format!(
+                "rate_of_fire {} should be positive",
+                self.rate_of_fire
+            ));
+        }
+        if self.spread < 0.0 {
+            return Err(format!("spread {} should not be negative", self.spread));
+        }
+        if self.burst.is_some_and(|burst| burst.shots == 0) {
+            return Err("burst shots should be positive".to_string());
+        }
+        if self.magazine.is_some_and(|magazine| magazine.size == 0) {
+            return Err("magazine size should be positive".to_string());
+        }
+        if let Some(heat) = &self.heat {
+            heat.validate()?;
+        }
+        self.projectile.validate()
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+struct Burst {
+    shots: u32,
+    /// Pause between bursts in seconds, added to the regular shot interval
+    pause: f32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+struct Bloom {
+    /// Spread added by each shot, in degrees
+    per_shot: f32,
+    /// Maximum added spread, in degrees
+    max: f32,
+    /// Added spread recovered per second while not firing, in degrees
+    recovery: f32,
+}
+
+impl Bloom {
+    fn to_radians(self) -> Self {
+        Self {
+            per_shot: self.per_shot.to_radians(),
+            max: self.max.to_radians(),
+            recovery: self.recovery.to_radians(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+struct Magazine {
+    size: u32,
+    /// Reload time in seconds, added to the regular shot interval after the last round
+    reload: f32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+struct Heat {
+    /// Heat added by each shot
+    per_shot: f32,
+    /// Heat at which the weapon overheats
+    capacity: f32,
+    cooling: Cooling,
+    /// Fraction of the capacity the heat should drop to before an overheated weapon can fire again
+    recover: f32,
+}
+
+impl Heat {
+    fn validate(&self) -> Result<(), String> {
+        if self.capacity <= 0.0 {
+            return Err(format!(
+                "heat capacity {} should be positive",
+                self.capacity
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.recover) {
+            return Err(format!(
+                "heat recover {} should be within [0, 1]",
+                self.recover
+            ));
+        }
+        match self.cooling {
+            Cooling::Linear(rate) if rate < 0.0 => {
+                Err(format!("linear cooling rate {rate} should not be negative"))
+            }
+            Cooling::Exponential(half_life) if half_life <= 0.0 => Err(format!(
+                "exponential cooling half-life {half_life} should be positive"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How fast the weapon cools down
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Cooling {
+    /// Constant heat dissipated per second
+    Linear(f32),
+    /// Seconds for the heat to halve, hot weapon cools down faster
+    Exponential(f32),
+}
+
+impl Cooling {
+    /// Heat after cooling down for `dt` seconds
+    pub(crate) fn cool(self, heat: f32, dt: f32) -> f32 {
+        match self {
+            Cooling::Linear(rate) => (heat - rate * dt).max(0.0),
+            Cooling::Exponential(half_life) => heat * 0.5f32.powf(dt / half_life),
+        }
+    }
+}
+
+/// Catalog entry of the projectile fired by a weapon
+#[derive(Deserialize, Clone, Debug)]
+struct ProjectileSpec {
+    /// Projectile is a capsule aligned with its velocity. Only the radius of shape casts counts
+    /// for hits, the rest of the size is visual.
+    radius: f32,
+    /// Full length of the capsule, should be at least twice the radius
+    length: f32,
+    /// sRGB color of the unlit projectile material
+    color: [f32; 3],
+    /// Speed relative to the weapon
+    speed: f32,
+    /// Seconds before the projectile is despawned if it doesn't hit anything
+    lifetime: f32,
+    damage: f32,
+    /// Mass in kilograms, zero for energy bolts that neither recoil nor push the target
+    #[serde(default)]
+    mass: f32,
+    #[serde(default)]
+    hit_detection: HitDetection,
+}
+
+impl ProjectileSpec {
+    fn validate(&self) -> Result<(), String> {
+        if self.speed <= 0.0 {
+            return Err(format!(
+                "projectile speed {} should be positive",
+                self.speed
+            ));
+        }
+        if self.lifetime <= 0.0 {
+            return Err(format!(
+                "projectile lifetime {} should be positive",
+                self.lifetime
+            ));
+        }
+        if self.radius <= 0.0 {
+            return Err(format!(
+                "projectile radius {} should be positive",
+                self.radius
+            ));
+        }
+        if self.length < 2.0 * self.radius {
+            return Err(format!(
+                "projectile length {} should be at least twice the radius {}",
+                self.length, self.radius
+            ));
+        }
+        if self.mass < 0.0 {
+            return Err(format!(
+                "projectile mass {} should not be negative",
+                self.mass
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Shared data to spawn projectiles of the same type
+pub(crate) struct ProjectileType {
     mesh: Handle<Mesh>,
     material: Handle<StandardMaterial>,
 
     speed: f32,
-    lifetime: Lifetime,
+    lifetime: f32,
+    damage: Damage,
+    mass: f32,
+    /// Radius of the sphere cast with [`HitDetection::Shape`], rays are cast without it
+    shape_cast: Option<f32>,
 }
 
-impl Projectile {
+impl ProjectileType {
     fn new(
-        meshes: &mut ResMut<Assets<Mesh>>,
-        materials: &mut ResMut<Assets<StandardMaterial>>,
+        spec: &ProjectileSpec,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
     ) -> Self {
-        let radius = 0.1;
+        let half_length = (spec.length / 2.0 - spec.radius).max(0.0);
+        let [red, green, blue] = spec.color;
         Self {
-            collider: Collider::capsule_y(8.0 * radius, radius),
             mesh: meshes.add(Mesh::from(Capsule3d {
-                radius,
-                half_length: 8.0 * radius,
+                radius: spec.radius,
+                half_length,
             })),
             material: materials.add(StandardMaterial {
-                base_color: Color::WHITE,
+                base_color: Color::srgb(red, green, blue),
                 // exclude this material from shadows calculations
                 unlit: true,
                 ..default()
             }),
-            lifetime: Lifetime(10.0),
-
-            speed: 100.0,
+            speed: spec.speed,
+            lifetime: spec.lifetime,
+            damage: Damage(spec.damage),
+            mass: spec.mass,
+            shape_cast: (spec.hit_detection == HitDetection::Shape).then_some(spec.radius),
         }
     }
 
-    fn spawn(&self, commands: &mut Commands, position: Vec3, direction: Vec3, velocity: Vec3) {
-        commands.spawn((
+    /// Projectile speed relative to the weapon
+    pub(crate) fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Seconds the projectile flies if it doesn't hit anything
+    pub(crate) fn lifetime(&self) -> f32 {
+        self.lifetime
+    }
+
+    /// Launches a bullet taken from the `pool`
+    pub(crate) fn spawn(
+        &self,
+        commands: &mut Commands,
+        pool: &mut ProjectilePool,
+        position: Vec3,
+        direction: Vec3,
+        velocity: Vec3,
+        owner: ProjectileOwner,
+    ) {
+        let mut bullet = Bullet::new(velocity, self.lifetime, self.mass);
+        if let Some(radius) = self.shape_cast {
+            bullet = bullet.with_shape_cast(radius);
+        }
+        // Every component is replaced, so nothing is left from the previous shot of a reused bullet
+        pool.acquire(commands).insert((
             Mesh3d(self.mesh.clone()),
             MeshMaterial3d(self.material.clone()),
             Transform {
                 translation: position,
-                // `Collider::capsule_y` and `shape::Capsule` are both aligned with Vec3::Y axis
+                // `shape::Capsule` is aligned with Vec3::Y axis
                 rotation: Quat::from_rotation_arc(Vec3::Y, direction),
                 scale: Vec3::ONE,
             },
-            self.lifetime.clone(),
-            // Change to RigidBody::Dynamic if projectile should be affected by gravity or other forces
-            RigidBody::KinematicVelocityBased,
-            Velocity {
-                linvel: velocity,
-                ..default()
-            },
-            self.collider.clone(),
-            // Use intersection graph with Sensor for simplicity
-            // Remove Sensor if contact graph is needed
-            Sensor,
+            Visibility::Inherited,
+            bullet,
+            self.damage,
             // Exclude projectile from shadows calculations
             NotShadowCaster,
             NotShadowReceiver,
+            Projectile,
+            owner,
+            VisualInterpolation::default(),
             Name::new("Projectile"),
         ));
     }
 }
 
-fn setup_projectile(
+/// Weapon catalog entry prepared for firing
+pub(crate) enum WeaponType {
+    Gun(GunType),
+    Beam(BeamType),
+    Launcher(LauncherType),
+}
+
+impl WeaponType {
+    /// Speed of projectiles relative to the weapon, `None` for beams that hit instantly
+    /// and for missiles that guide themselves
+    pub(crate) fn projectile_speed(&self) -> Option<f32> {
+        match self {
+            WeaponType::Gun(gun) => Some(gun.projectile.speed()),
+            WeaponType::Beam(_) | WeaponType::Launcher(_) => None,
+        }
+    }
+
+    /// Seconds between shots or launches, `None` for beams firing continuously
+    pub(crate) fn shot_interval(&self) -> Option<f32> {
+        match self {
+            WeaponType::Gun(gun) => Some(gun.shot_interval),
+            WeaponType::Beam(_) => None,
+            WeaponType::Launcher(launcher) => Some(launcher.reload()),
+        }
+    }
+}
+
+/// Gun catalog entry prepared for spawning projectiles
+pub(crate) struct GunType {
+    /// Interval between shots in seconds at the full rate of fire
+    shot_interval: f32,
+    spin_up: f32,
+    burst: Option<Burst>,
+    /// Spread cone half-angle in radians
+    spread: f32,
+    /// Bloom with angles in radians
+    bloom: Option<Bloom>,
+    magazine: Option<Magazine>,
+    heat: Option<Heat>,
+    energy_per_shot: f32,
+    recoil: f32,
+    projectile: ProjectileType,
+}
+
+impl GunType {
+    pub(crate) fn projectile(&self) -> &ProjectileType {
+        &self.projectile
+    }
+
+    /// Spin-up progress after `dt` seconds, rising while firing and falling back otherwise
+    fn spin(&self, spin: f32, is_firing: bool, dt: f32) -> f32 {
+        if self.spin_up <= 0.0 {
+            return 1.0;
+        }
+        let change = dt / self.spin_up;
+        if is_firing {
+            (spin + change).min(1.0)
+        } else {
+            (spin - change).max(0.0)
+        }
+    }
+}
+
+/// All weapons of the catalog by name
+#[derive(Resource, Default)]
+pub(crate) struct WeaponTypes(HashMap<String, WeaponType>);
+
+impl WeaponTypes {
+    /// Prepares every weapon of the catalog for spawning, fails on the first invalid entry
+    fn from_catalog(
+        catalog: &WeaponCatalog,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Result<Self, String> {
+        let weapon_types = catalog
+            .weapons
+            .iter()
+            .map(|(name, spec)| {
+                match spec {
+                    WeaponSpec::Gun(spec) => spec.validate(),
+                    WeaponSpec::Beam(spec) => spec.validate(),
+                    WeaponSpec::Launcher(spec) => spec.validate(),
+                }
+                .map_err(|error| format!("weapon \"{name}\": {error}"))?;
+                let weapon_type = match spec {
+                    WeaponSpec::Gun(spec) => WeaponType::Gun(GunType {
+                        shot_interval: 1.0 / spec.rate_of_fire,
+                        spin_up: spec.spin_up,
+                        burst: spec.burst,
+                        spread: spec.spread.to_radians(),
+                        bloom: spec.bloom.map(Bloom::to_radians),
+                        magazine: spec.magazine,
+                        heat: spec.heat,
+                        energy_per_shot: spec.energy_per_shot,
+                        recoil: spec.recoil,
+                        projectile: ProjectileType::new(&spec.projectile, meshes, materials),
+                    }),
+                    WeaponSpec::Beam(spec) => {
+                        WeaponType::Beam(BeamType::new(spec, meshes, materials))
+                    }
+                    WeaponSpec::Launcher(spec) => {
+                        WeaponType::Launcher(LauncherType::new(spec, meshes, materials))
+                    }
+                };
+                Ok((name.clone(), weapon_type))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self(weapon_types))
+    }
+
+    pub(crate) fn get(&self, weapon: &Weapon) -> Option<&WeaponType> {
+        self.named(&weapon.kind)
+    }
+
+    /// Weapon by its name in the [`WeaponCatalog`]
+    pub(crate) fn named(&self, name: &str) -> Option<&WeaponType> {
+        self.0.get(name)
+    }
+}
+
+/// Mesh and material of the sparks spawned on hits
+#[derive(Resource)]
+struct HitEffect {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl HitEffect {
+    fn new(meshes: &mut Assets<Mesh>, materials: &mut Assets<StandardMaterial>) -> Self {
+        let radius = 0.05;
+        Self {
+            mesh: meshes.add(Mesh::from(Capsule3d {
+                radius,
+                half_length: 8.0 * radius,
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::WHITE,
+                unlit: true,
+                ..default()
+            }),
+        }
+    }
+}
+
+fn setup_weapon_types(
     mut commands: Commands,
+    catalogs: Res<Catalogs>,
+    catalog_assets: Res<Assets<WeaponCatalog>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    let projectile = Projectile::new(&mut meshes, &mut materials);
-    commands.insert_resource(projectile);
+    let catalog = catalog_assets
+        .get(&catalogs.weapons)
+        .expect("weapon catalog is loaded");
+    let weapon_types = WeaponTypes::from_catalog(catalog, &mut meshes, &mut materials)
+        .unwrap_or_else(|error| panic!("invalid weapon catalog: {error}"));
+    commands.insert_resource(weapon_types);
+    commands.insert_resource(HitEffect::new(&mut meshes, &mut materials));
+}
+
+/// Distance ahead of the ship at which barrels off the ship axis cross it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Convergence {
+    /// Used unless there is a target to converge on
+    distance: f32,
+    /// Cone half-angle in radians and range to find the target in
+    target: Option<(f32, f32)>,
+}
+
+impl Convergence {
+    pub(crate) fn at(distance: f32) -> Self {
+        Self {
+            distance,
+            target: None,
+        }
+    }
+
+    /// Converge at the distance of the hostile ship closest to the ship axis within the cone
+    /// of `max_angle` and the `range`
+    pub(crate) fn with_target(mut self, max_angle: f32, range: f32) -> Self {
+        self.target = Some((max_angle, range));
+        self
+    }
 }
 
 #[derive(Component)]
 pub(crate) struct Weapon {
+    /// Name of the weapon in the [`WeaponCatalog`]
+    kind: String,
+    group: WeaponGroup,
+    /// Shots leave along the barrel without it
+    convergence: Option<Convergence>,
     is_firing: bool,
-    /// Interval between shots in seconds
-    shot_interval: f32,
+    /// Multiplier of the shot interval set by the group fire mode, see [`crate::fire_control::firing_schedule`]
+    pub(crate) interval_scale: f32,
     /// Weapon cooldown timer in seconds. Cannot be negative outside of [`weapon_fire`] system.
-    cooldown: f32,
+    pub(crate) cooldown: f32,
+    /// Spin-up progress from 0 when idle to 1 at the full rate of fire
+    spin: f32,
+    /// Shots fired since the last burst pause
+    burst_shots: u32,
+    /// Rounds fired from the current magazine
+    rounds_fired: u32,
+    heat: f32,
+    /// Locked out until the heat drops to the recover level
+    overheated: bool,
+    /// Spread added by sustained fire in radians
+    bloom: f32,
+    /// Beam charge progress from 0 to 1, see [`crate::beam::charge_beam`]
+    pub(crate) charge: f32,
+    /// Visual of the emitted beam
+    pub(crate) beam: Option<Entity>,
+    /// Target tracked by the missile launcher seeker
+    pub(crate) lock: Option<SeekerLock>,
 }
 
 impl Weapon {
-    pub(crate) fn new(rate_of_fire: f32) -> Self {
+    pub(crate) fn new(kind: &str) -> Self {
         Self {
+            kind: kind.to_owned(),
+            group: WeaponGroup::Primary,
+            convergence: None,
             is_firing: false,
-            shot_interval: 1.0 / rate_of_fire,
+            interval_scale: 1.0,
             cooldown: 0.0,
+            spin: 0.0,
+            burst_shots: 0,
+            rounds_fired: 0,
+            heat: 0.0,
+            overheated: false,
+            bloom: 0.0,
+            charge: 0.0,
+            beam: None,
+            lock: None,
         }
     }
 
+    pub(crate) fn with_group(mut self, group: WeaponGroup) -> Self {
+        self.group = group;
+        self
+    }
+
+    pub(crate) fn with_convergence(mut self, convergence: Convergence) -> Self {
+        self.convergence = Some(convergence);
+        self
+    }
+
+    pub(crate) fn group(&self) -> WeaponGroup {
+        self.group
+    }
+
     pub(crate) fn fire(&mut self) {
         self.is_firing = true;
     }
+
+    /// Returns whether the weapon was fired since the last tick and resets the trigger.
+    /// `is_firing` should be set each tick by input system.
+    pub(crate) fn take_trigger(&mut self) -> bool {
+        std::mem::take(&mut self.is_firing)
+    }
 }
 
+/// Rigid body state needed to find the velocity of its points, see [`weapon_shooter`]
+pub(crate) type ShipMotion = (
+    &'static Velocity,
+    &'static GlobalTransform,
+    Option<&'static ReadMassProperties>,
+);
+
+/// World space center of mass of a rigid body
+pub(crate) fn center_of_mass(
+    transform: &GlobalTransform,
+    mass: Option<&ReadMassProperties>,
+) -> Vec3 {
+    // Mass properties are known after the first physics step, the body origin is close enough before that
+    mass.map_or(transform.translation(), |mass| {
+        transform.transform_point(mass.get().local_center_of_mass)
+    })
+}
+
+/// Ship the weapon belongs to, the first ancestor with `Velocity`, and the velocity of the ship
+/// at the `muzzle` point in world space. Barrels of a rotating ship move faster the farther
+/// they are from the center of mass, so projectiles inherit the point velocity, not just the linear one.
+pub(crate) fn weapon_shooter(
+    weapon: Entity,
+    muzzle: Vec3,
+    parents: &Query<&Parent>,
+    ships: &Query<ShipMotion>,
+) -> (Option<Entity>, Vec3) {
+    parents
+        .iter_ancestors(weapon)
+        .find_map(|parent| {
+            ships.get(parent).ok().map(|(velocity, transform, mass)| {
+                let center = center_of_mass(transform, mass);
+                (
+                    Some(parent),
+                    velocity.linear_velocity_at_point(muzzle, center),
+                )
+            })
+        })
+        .unwrap_or((None, Vec3::ZERO))
+}
+
+/// Random direction within the cone of `spread` half-angle around `forward`, uniformly distributed
+/// over the solid angle. `u` and `v` are uniform random numbers in `[0, 1)`.
+pub(crate) fn spread_direction(forward: Vec3, spread: f32, u: f32, v: f32) -> Vec3 {
+    let cos = 1.0 - u * (1.0 - spread.cos());
+    let sin = (1.0 - cos * cos).max(0.0).sqrt();
+    let (sin_azimuth, cos_azimuth) = (v * std::f32::consts::TAU).sin_cos();
+    let local = Vec3::new(sin * cos_azimuth, sin * sin_azimuth, cos);
+    Quat::from_rotation_arc(Vec3::Z, forward) * local
+}
+
+/// Barrel direction towards the point at the `distance` along the ship `axis` from the ship `origin`.
+/// The barrel `forward` is kept if the point is not in front of the `muzzle`.
+pub(crate) fn converge(
+    muzzle: Vec3,
+    forward: Vec3,
+    origin: Vec3,
+    axis: Vec3,
+    distance: f32,
+) -> Vec3 {
+    (origin + axis * distance - muzzle)
+        .try_normalize()
+        .filter(|direction| direction.dot(forward) > 0.0)
+        .unwrap_or(forward)
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn weapon_fire(
     mut commands: Commands,
-    projectile: Res<Projectile>,
+    weapon_types: Res<WeaponTypes>,
+    mut rng: ResMut<SimulationRng>,
+    mut pool: ResMut<ProjectilePool>,
     mut query: Query<(Entity, &mut Weapon, &GlobalTransform)>,
+    mut power_pools: Query<&mut PowerPool>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    factions: Query<&Faction>,
+    targets: Query<(Entity, &GlobalTransform, Option<&Faction>), With<Hull>>,
     time: Res<Time>,
-    velocity_query: Query<&Velocity>,
+    ships: Query<ShipMotion>,
     parent_query: Query<&Parent>,
 ) {
+    let dt = time.delta_secs();
     for (entity, mut weapon, transform) in query.iter_mut() {
+        let weapon_type = match weapon_types.get(&weapon) {
+            Some(WeaponType::Gun(gun)) => gun,
+            // Fired by `fire_beams` and `fire_missiles`
+            Some(WeaponType::Beam(_) | WeaponType::Launcher(_)) => continue,
+            None => {
+                warn_once!("Weapon {} is missing in the catalog", weapon.kind);
+                continue;
+            }
+        };
+        let is_firing = weapon.take_trigger();
+        weapon.spin = weapon_type.spin(weapon.spin, is_firing, dt);
         if weapon.cooldown > 0.0 {
             // Tick cooldown only if greater than zero to avoid negative value on first frame of firing.
             // Negative values than are used to calculate offset time for projectile spawn to keep constant fire rate.
-            weapon.cooldown -= time.delta_secs();
+            // Spinning weapon fires slower, as if the cooldown ticks slower, while reloads continue at the normal pace.
+            weapon.cooldown -= if is_firing { dt * weapon.spin } else { dt };
+        }
+        if let Some(heat) = weapon_type.heat {
+            weapon.heat = heat.cooling.cool(weapon.heat, dt);
+            if weapon.overheated && weapon.heat <= heat.recover * heat.capacity {
+                weapon.overheated = false;
+            }
         }
-        if !weapon.is_firing {
+        if !is_firing {
             weapon.cooldown = weapon.cooldown.max(0.0);
+            // Next burst starts from the beginning once the trigger is released
+            weapon.burst_shots = 0;
+            // Sustained fire keeps the bloom, it recovers only while the trigger is released
+            if let Some(bloom) = weapon_type.bloom {
+                weapon.bloom = (weapon.bloom - bloom.recovery * dt).max(0.0);
+            }
             continue;
         }
-        // `weapon.is_firing` should be set each frame by input system
-        weapon.is_firing = false;
 
-        // resolve own velocity from parent if any
-        let gun_velocity = parent_query
-            .iter_ancestors(entity)
-            .filter_map(|parent| velocity_query.get(parent).ok())
-            .map(|velocity| velocity.linvel)
-            .next()
-            .unwrap_or(Vec3::ZERO);
+        // resolve own velocity and shooter ship from parent if any
+        let (shooter, gun_velocity) =
+            weapon_shooter(entity, transform.translation(), &parent_query, &ships);
+        let owner = ProjectileOwner::new(shooter, &factions);
+        let recoil = weapon_type.projectile.mass * weapon_type.recoil;
+        let ship = shooter.and_then(|shooter| ships.get(shooter).ok());
+        let ship_center = ship.map(|(_, transform, mass)| center_of_mass(transform, mass));
 
+        let mut forward: Vec3 = transform.forward().into();
+        if let (Some(convergence), Some((_, ship_transform, _))) = (weapon.convergence, ship) {
+            let origin = ship_transform.translation();
+            let axis: Vec3 = ship_transform.forward().into();
+            let distance = convergence
+                .target
+                .map_or(convergence.distance, |(max_angle, range)| {
+                    // Allies are never converged on, even with friendly fire
+                    let candidates = targets
+                        .iter()
+                        .filter(|(target, _, faction)| {
+                            owner.can_hit(*target, faction.copied(), FriendlyFire::Off)
+                        })
+                        .map(|(target, transform, _)| (target, transform.translation()));
+                    seeker_candidate(origin, axis, max_angle, range, candidates)
+                        .and_then(|target| targets.get(target).ok())
+                        .map_or(convergence.distance, |(_, transform, _)| {
+                            (transform.translation() - origin).dot(axis)
+                        })
+                });
+            forward = converge(transform.translation(), forward, origin, axis, distance);
+        }
+
+        let projectile = &weapon_type.projectile;
         while weapon.cooldown <= 0.0 {
-            // time in the past from the current frame when projectile should be spawned
-            let offset_time = -weapon.cooldown;
-            weapon.cooldown += weapon.shot_interval;
+            // Energy is drawn only if the weapon can fire, ships without a power pool fire for free
+            let is_blocked = weapon.overheated
+                || weapon_type.energy_per_shot > 0.0
+                    && shooter
+                        .and_then(|shooter| power_pools.get_mut(shooter).ok())
+                        .is_some_and(|mut pool| !pool.draw(weapon_type.energy_per_shot));
+            if is_blocked {
+                // Fires as soon as the weapon is ready again, the time it was waiting doesn't count
+                weapon.cooldown = 0.0;
+                break;
+            }
 
-            let direction = transform.forward();
+            // time in the past from the current frame when projectile should be spawned.
+            // Spin is positive while firing, and the cooldown went below zero during the last `dt * spin`.
+            let offset_time = -weapon.cooldown / weapon.spin;
+            weapon.cooldown += weapon_type.shot_interval * weapon.interval_scale;
+            if let Some(magazine) = weapon_type.magazine {
+                weapon.rounds_fired += 1;
+                if weapon.rounds_fired >= magazine.size {
+                    weapon.rounds_fired = 0;
+                    weapon.cooldown += magazine.reload;
+                }
+            }
+            if let Some(heat) = weapon_type.heat {
+                weapon.heat += heat.per_shot;
+                weapon.overheated = weapon.heat >= heat.capacity;
+            }
+            if let Some(burst) = weapon_type.burst {
+                weapon.burst_shots += 1;
+                if weapon.burst_shots >= burst.shots {
+                    weapon.burst_shots = 0;
+                    weapon.cooldown += burst.pause;
+                }
+            }
+
+            // Random numbers are drawn only for spread weapons, so others don't change the sequence
+            let spread = weapon_type.spread + weapon.bloom;
+            let direction = if spread > 0.0 {
+                spread_direction(forward, spread, rng.next_f32(), rng.next_f32())
+            } else {
+                forward
+            };
+            if let Some(bloom) = weapon_type.bloom {
+                weapon.bloom = (weapon.bloom + bloom.per_shot).min(bloom.max);
+            }
             // relative velocity of projectile to gun
             let rel_velocity = direction * projectile.speed;
             // move projectile spawn point forward to handle case when multiple projectiles are spawned
             let position = transform.translation() + rel_velocity * offset_time;
             let velocity = rel_velocity + gun_velocity;
 
-            projectile.spawn(&mut commands, position, direction.into(), velocity);
+            projectile.spawn(
+                &mut commands,
+                &mut pool,
+                position,
+                direction,
+                velocity,
+                owner,
+            );
+
+            // Pushes the ship back at the barrel, turning it if the barrel is off the center of mass
+            if let (Some(center), true) = (ship_center, recoil > 0.0) {
+                if let Some(mut impulse) =
+                    shooter.and_then(|shooter| impulses.get_mut(shooter).ok())
+                {
+                    *impulse += ExternalImpulse::at_point(
+                        -rel_velocity * recoil,
+                        transform.translation(),
+                        center,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Entity hit by the collider: the rigid body the collider is attached to, or the collider itself
+pub(crate) fn hit_entity(
+    collider: Entity,
+    bodies: &Query<(), With<RigidBody>>,
+    parents: &Query<&Parent>,
+) -> Entity {
+    std::iter::once(collider)
+        .chain(parents.iter_ancestors(collider))
+        .find(|entity| bodies.contains(*entity))
+        .unwrap_or(collider)
+}
+
+/// Turns sensor intersections of missiles into hits
+fn detect_sensor_hits(
+    time: Res<Time>,
+    mut collisions: EventReader<CollisionEvent>,
+    projectiles: Query<(
+        &ProjectileOwner,
+        &Damage,
+        &Transform,
+        &Velocity,
+        Option<&ReadMassProperties>,
+    )>,
+    colliders: Query<(&Collider, &GlobalTransform)>,
+    bodies: Query<(), With<RigidBody>>,
+    parents: Query<&Parent>,
+    mut hits: EventWriter<ProjectileHit>,
+) {
+    // Projectile can intersect several colliders during a tick, only the first one is hit
+    let mut spent = Vec::new();
+    for event in collisions.read() {
+        let CollisionEvent::Started(collider1, collider2, _) = *event else {
+            continue;
+        };
+        let (projectile_entity, other) = if projectiles.contains(collider1) {
+            (collider1, collider2)
+        } else if projectiles.contains(collider2) {
+            (collider2, collider1)
+        } else {
+            continue;
+        };
+        let (owner, damage, transform, velocity, mass) =
+            projectiles.get(projectile_entity).unwrap();
+        let target = hit_entity(other, &bodies, &parents);
+        // Collision groups filter out other projectiles and, without friendly fire, allied ships.
+        // The owner ship is in the same group, so it has to be skipped even with friendly fire.
+        if spent.contains(&projectile_entity) || owner.ship == Some(target) {
+            continue;
+        }
+        spent.push(projectile_entity);
+
+        // Intersection events have no contact data, so the hit is found by casting the path
+        // travelled during the tick against the target collider
+        let direction = velocity.linvel.normalize_or(*transform.up());
+        let travelled = velocity.linvel.length() * time.delta_secs();
+        let start = transform.translation - direction * travelled;
+        let (point, normal) = colliders
+            .get(other)
+            .ok()
+            .and_then(|(collider, collider_transform)| {
+                let (_, rotation, translation) = collider_transform.to_scale_rotation_translation();
+                // Extended by the projectile size, as the intersection could start with its tip
+                collider.cast_ray_and_get_normal(
+                    translation,
+                    rotation,
+                    start,
+                    direction,
+                    travelled * 2.0 + 1.0,
+                    true,
+                )
+            })
+            .map(|hit| (hit.point, hit.normal))
+            .unwrap_or((transform.translation, -direction));
+
+        hits.send(ProjectileHit {
+            projectile: projectile_entity,
+            shooter: owner.ship,
+            target,
+            point,
+            normal,
+            damage: damage.0,
+            velocity: velocity.linvel,
+            mass: mass.map_or(0.0, |mass| mass.get().mass),
+        });
+    }
+}
+
+/// Damages and pushes the target, then despawns the projectile, bullets are returned to the pool instead.
+/// Projectiles hit by other projectiles are shot down and despawned without exploding.
+/// The impulse is applied during the next physics step.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn apply_projectile_hits(
+    mut commands: Commands,
+    mut pool: ResMut<ProjectilePool>,
+    mut bullets: Query<(&mut Bullet, &mut Visibility)>,
+    mut hits: EventReader<ProjectileHit>,
+    mut hulls: Query<&mut Hull>,
+    mut targets: Query<(
+        &Velocity,
+        &GlobalTransform,
+        Option<&ReadMassProperties>,
+        &mut ExternalImpulse,
+    )>,
+    explosives: Query<&Explosive>,
+    projectiles: Query<(), With<Projectile>>,
+    mut hull_hits: EventWriter<HullHit>,
+    mut destroyed: EventWriter<HullDestroyed>,
+    mut explosions: EventWriter<Explosion>,
+) {
+    for hit in hits.read() {
+        // Only dynamic bodies with `ExternalImpulse` are pushed
+        if hit.mass > 0.0 {
+            if let Ok((velocity, transform, mass, mut impulse)) = targets.get_mut(hit.target) {
+                // Momentum relative to the hit point of the target, so a ship flying into slow
+                // projectiles is slowed down instead of being pushed forward
+                let center = center_of_mass(transform, mass);
+                let relative = hit.velocity - velocity.linear_velocity_at_point(hit.point, center);
+                *impulse += ExternalImpulse::at_point(relative * hit.mass, hit.point, center);
+            }
+        }
+        if let Ok(mut hull) = hulls.get_mut(hit.target) {
+            let was_destroyed = hull.damage(hit.damage);
+            hull_hits.send(HullHit {
+                entity: hit.target,
+                other: hit.shooter.unwrap_or(hit.projectile),
+                damage: hit.damage,
+            });
+            if was_destroyed {
+                destroyed.send(HullDestroyed { entity: hit.target });
+            }
+        }
+        // Missile may be shot down and hit something during the same tick
+        if projectiles.contains(hit.target) {
+            commands.entity(hit.target).try_despawn_recursive();
+        }
+        if let Ok(explosive) = explosives.get(hit.projectile) {
+            explosions.send(Explosion {
+                center: hit.point,
+                spec: explosive.0,
+                cause: hit.shooter.unwrap_or(hit.projectile),
+            });
+        }
+        match bullets.get_mut(hit.projectile) {
+            Ok((mut bullet, mut visibility)) => {
+                pool.release(hit.projectile, &mut bullet, &mut visibility)
+            }
+            Err(_) => commands.entity(hit.projectile).try_despawn_recursive(),
+        }
+    }
+}
+
+/// Short spark pointing out of the hit surface
+fn hit_effects(
+    mut commands: Commands,
+    effect: Res<HitEffect>,
+    mut hits: EventReader<ProjectileHit>,
+) {
+    for hit in hits.read() {
+        debug!("{} hit by {:?} at {}", hit.target, hit.shooter, hit.point);
+        commands.spawn((
+            Mesh3d(effect.mesh.clone()),
+            MeshMaterial3d(effect.material.clone()),
+            Transform::from_translation(hit.point)
+                .with_rotation(Quat::from_rotation_arc(Vec3::Y, hit.normal)),
+            Lifetime(0.15),
+            NotShadowCaster,
+            NotShadowReceiver,
+            Name::new("Hit"),
+        ));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        explosion::ExplosionPlugin,
+        hull::HullPlugin,
+        power::PowerPlugin,
+        simulation::{test_app, SimulationTick, DEFAULT_TICK_RATE},
+    };
+
+    /// Headless simulation with the weapons of the game catalog. Hits are collected into [`RecordedHits`].
+    pub(crate) fn weapon_app() -> App {
+        let catalog: WeaponCatalog =
+            ron::from_str(include_str!("../assets/catalog.weapons.ron")).unwrap();
+        let mut meshes = Assets::<Mesh>::default();
+        let mut materials = Assets::<StandardMaterial>::default();
+        let mut app = test_app();
+        app.insert_resource(
+            WeaponTypes::from_catalog(&catalog, &mut meshes, &mut materials).unwrap(),
+        )
+        .insert_resource(HitEffect::new(&mut meshes, &mut materials))
+        .insert_resource(meshes)
+        .insert_resource(materials)
+        .init_resource::<RecordedHits>()
+        .add_plugins((WeaponPlugin, HullPlugin, ExplosionPlugin, PowerPlugin))
+        .add_systems(FixedUpdate, record_hits.after(apply_projectile_hits));
+        app
+    }
+
+    /// Every projectile hit since the start
+    #[derive(Resource, Default)]
+    pub(crate) struct RecordedHits(pub(crate) Vec<ProjectileHit>);
+
+    fn record_hits(mut hits: EventReader<ProjectileHit>, mut recorded: ResMut<RecordedHits>) {
+        recorded.0.extend(hits.read().cloned());
+    }
+
+    /// Keeps the trigger of every weapon pulled, should run before [`WeaponFireSet`]
+    pub(crate) fn hold_triggers(mut weapons: Query<&mut Weapon>) {
+        for mut weapon in weapons.iter_mut() {
+            weapon.fire();
+        }
+    }
+
+    /// Dynamic ship with a hull, weapons can be attached as children
+    pub(crate) fn ship(position: Vec3) -> impl Bundle {
+        (
+            Transform::from_translation(position),
+            RigidBody::Dynamic,
+            Collider::ball(2.0),
+            Velocity::default(),
+            ExternalImpulse::default(),
+            Hull::new(100.0),
+        )
+    }
+
+    /// Static target with a hull that takes a while to destroy
+    pub(crate) fn target(position: Vec3) -> impl Bundle {
+        (
+            Transform::from_translation(position),
+            RigidBody::Fixed,
+            Collider::ball(3.0),
+            Hull::new(1000.0),
+        )
+    }
+
+    pub(crate) fn run(app: &mut App, seconds: f32) {
+        let ticks = seconds * crate::simulation::DEFAULT_TICK_RATE as f32;
+        for _ in 0..ticks as usize {
+            app.update();
+        }
+    }
+
+    #[test]
+    fn projectiles_hit_static_target() {
+        let mut app = weapon_app();
+        app.add_systems(FixedUpdate, hold_triggers.before(WeaponFireSet));
+        let world = app.world_mut();
+        // Barrel is inside the shooter collider, so its own ship is on the path of every shot
+        let shooter = world
+            .spawn(ship(Vec3::ZERO))
+            .with_child((Transform::default(), Weapon::new("autocannon")))
+            .id();
+        let target = world.spawn(target(Vec3::new(0.0, 0.0, -50.0))).id();
+
+        run(&mut app, 2.0);
+
+        let world = app.world();
+        let hits = &world.resource::<RecordedHits>().0;
+        assert!(!hits.is_empty());
+        for hit in hits {
+            assert_eq!(hit.target, target);
+            assert_eq!(hit.shooter, Some(shooter));
+            assert!(hit.point.z > -50.0 && hit.point.distance(Vec3::new(0.0, 0.0, -50.0)) < 3.1);
+        }
+        let integrity = world.get::<Hull>(target).unwrap().integrity();
+        assert_eq!(integrity, 1000.0 - 10.0 * hits.len() as f32);
+        assert_eq!(world.get::<Hull>(shooter).unwrap().integrity(), 100.0);
+    }
+
+    #[test]
+    fn autocannon_draws_ship_power() {
+        let mut app = weapon_app();
+        app.add_systems(FixedUpdate, hold_triggers.before(WeaponFireSet));
+        let world = app.world_mut();
+        let shooter = world
+            .spawn((ship(Vec3::ZERO), PowerPool::new(100.0, 25.0)))
+            .with_children(|ship| {
+                for x in [-1.0, 1.0] {
+                    ship.spawn((Transform::from_xyz(x, 0.0, -3.0), Weapon::new("autocannon")));
+                }
+            })
+            .id();
+
+        run(&mut app, 2.0);
+
+        // Two barrels spend 2 energy per shot, more than the pool recharges
+        let energy = app.world().get::<PowerPool>(shooter).unwrap().energy();
+        assert!(energy < 100.0, "{energy}");
+    }
+
+    #[test]
+    fn projectiles_inherit_velocity_of_spinning_barrel() {
+        let mut app = weapon_app();
+        app.add_systems(FixedUpdate, hold_triggers.before(WeaponFireSet));
+        // Rolls around the barrel direction, so the barrel keeps facing -Z while circling the axis
+        let spin = 2.0;
+        app.world_mut()
+            .spawn(ship(Vec3::ZERO))
+            .insert(Velocity::angular(Vec3::Z * spin))
+            .with_child((
+                Transform::from_xyz(5.0, 0.0, 0.0),
+                Weapon::new("autocannon"),
+            ));
+
+        let first_bullet = |app: &mut App| {
+            let world = app.world_mut();
+            world
+                .query::<(Entity, &Bullet)>()
+                .iter(world)
+                .find(|(_, bullet)| bullet.is_live())
+                .map(|(entity, _)| entity)
+        };
+        let bullet = loop {
+            app.update();
+            if let Some(bullet) = first_bullet(&mut app) {
+                break bullet;
+            }
+        };
+        // Bullets fired between ticks cover only a part of the tick distance on the first tick
+        app.update();
+        let position = |app: &App| app.world().get::<Transform>(bullet).unwrap().translation;
+        let start = position(&app);
+        run(&mut app, 0.5);
+        let end = position(&app);
+
+        let velocity = (end - start) / 0.5;
+        assert!((velocity.z + 100.0).abs() < 1.0, "{velocity}");
+        // Barrel moves around the axis at 10 m/s, spread alone is below 0.6 m/s
+        let tangential = velocity.xy().length();
+        assert!((tangential - 5.0 * spin).abs() < 1.0, "{velocity}");
+        // Tangential to the circle of the barrel, not pushed away from the axis
+        assert!(
+            velocity.xy().dot(start.xy()).abs() < start.xy().length(),
+            "{velocity}"
+        );
+    }
+
+    #[test]
+    fn owners_hit_other_factions_or_everyone_with_friendly_fire() {
+        use Faction::*;
+        use FriendlyFire::{Off, On};
+        let mut world = World::new();
+        let [shooter, ally, enemy] = [(); 3].map(|_| world.spawn_empty().id());
+        let owner = ProjectileOwner {
+            ship: Some(shooter),
+            faction: Some(Alliance),
+        };
+        let unaffiliated = ProjectileOwner {
+            ship: Some(shooter),
+            faction: None,
+        };
+        for (owner, target, faction, friendly_fire, hit) in [
+            (owner, shooter, Some(Alliance), On, false),
+            (owner, ally, Some(Alliance), Off, false),
+            (owner, ally, Some(Alliance), On, true),
+            (owner, enemy, Some(Raiders), Off, true),
+            (owner, enemy, None, Off, true),
+            (unaffiliated, ally, Some(Alliance), Off, true),
+            (unaffiliated, shooter, None, On, false),
+        ] {
+            assert_eq!(
+                owner.can_hit(target, faction, friendly_fire),
+                hit,
+                "{owner:?} {target} {faction:?} {friendly_fire:?}"
+            );
+        }
+
+        let own = owner;
+        let allied = ProjectileOwner {
+            ship: Some(ally),
+            faction: Some(Alliance),
+        };
+        let hostile = ProjectileOwner {
+            ship: Some(enemy),
+            faction: Some(Raiders),
+        };
+        let neutral = ProjectileOwner::default();
+        for (other, friendly_fire, hit) in [
+            (own, On, false),
+            (allied, Off, false),
+            (allied, On, true),
+            (hostile, Off, true),
+            (neutral, Off, true),
+        ] {
+            assert_eq!(
+                owner.can_hit_projectile(&other, friendly_fire),
+                hit,
+                "{other:?} {friendly_fire:?}"
+            );
+        }
+        // Projectiles without a shooter can still shoot down each other
+        assert!(neutral.can_hit_projectile(&neutral, Off));
+    }
+
+    #[test]
+    fn projectiles_pass_through_allies_without_friendly_fire() {
+        for friendly_fire in [FriendlyFire::Off, FriendlyFire::On] {
+            let mut app = weapon_app();
+            app.insert_resource(friendly_fire)
+                .add_systems(FixedUpdate, hold_triggers.before(WeaponFireSet));
+            let world = app.world_mut();
+            // Barrel is inside the shooter collider, so its own ship is on the path of every shot
+            let shooter = world
+                .spawn((ship(Vec3::ZERO), Faction::Alliance.bundle()))
+                .with_child((Transform::default(), Weapon::new("autocannon")))
+                .id();
+            let ally = world
+                .spawn((
+                    target(Vec3::new(0.0, 0.0, -25.0)),
+                    Faction::Alliance.bundle(),
+                ))
+                .id();
+            let enemy = world
+                .spawn((
+                    target(Vec3::new(0.0, 0.0, -50.0)),
+                    Faction::Raiders.bundle(),
+                ))
+                .id();
+
+            run(&mut app, 1.0);
+
+            let hits = &app.world().resource::<RecordedHits>().0;
+            assert!(!hits.is_empty(), "{friendly_fire:?}");
+            let expected = match friendly_fire {
+                FriendlyFire::Off => enemy,
+                FriendlyFire::On => ally,
+            };
+            for hit in hits {
+                assert_eq!(hit.target, expected, "{friendly_fire:?}");
+            }
+            let integrity = app.world().get::<Hull>(shooter).unwrap().integrity();
+            assert_eq!(integrity, 100.0, "{friendly_fire:?}");
+        }
+    }
+
+    #[test]
+    fn shots_push_shooter_back_and_target_away() {
+        // Autocannon rounds weigh 0.5 kg at 100 m/s, blaster bolts are massless
+        for (weapon, round_momentum) in [("autocannon", 50.0), ("blaster", 0.0)] {
+            let mut app = weapon_app();
+            app.add_systems(
+                FixedUpdate,
+                hold_triggers
+                    .before(WeaponFireSet)
+                    .run_if(|time: Res<Time>| time.elapsed_secs() < 1.0),
+            );
+            let world = app.world_mut();
+            // Heavy enough that neither the shooter recoil nor the target motion change
+            // the relative speed of later rounds by much
+            let shooter = world
+                .spawn((ship(Vec3::ZERO), ColliderMassProperties::Density(1000.0)))
+                .with_child((Transform::default(), Weapon::new(weapon)))
+                .id();
+            let target = world
+                .spawn((
+                    ship(Vec3::new(0.0, 0.0, -50.0)),
+                    ColliderMassProperties::Density(10.0),
+                ))
+                .insert(Hull::new(1000.0))
+                .id();
+
+            // Every round reaches the target before the end
+            run(&mut app, 2.0);
+
+            let world = app.world_mut();
+            let live = world
+                .query::<&Bullet>()
+                .iter(world)
+                .filter(|bullet| bullet.is_live())
+                .count();
+            assert_eq!(live, 0, "{weapon}");
+            let shots = world.resource::<RecordedHits>().0.len() as f32;
+            assert!(shots > 0.0, "{weapon}");
+            // Balls of radius 2 with the given density
+            let momentum = |entity, density: f32| {
+                let mass = density * 4.0 / 3.0 * std::f32::consts::PI * 8.0;
+                world.get::<Velocity>(entity).unwrap().linvel * mass
+            };
+            let expected = Vec3::Z * shots * round_momentum;
+            let recoil = momentum(shooter, 1000.0);
+            assert!(
+                recoil.distance(expected) <= 0.01 * expected.length() + 1e-3,
+                "{weapon} {shots} {recoil}"
+            );
+            let push = momentum(target, 10.0);
+            assert!(
+                push.distance(-expected) <= 0.02 * expected.length() + 1e-3,
+                "{weapon} {shots} {push}"
+            );
+        }
+    }
+
+    #[test]
+    fn spread_directions_stay_inside_cone() {
+        let spread = 2f32.to_radians();
+        let forwards = [Vec3::NEG_Z, Vec3::Z, Vec3::new(1.0, -2.0, 0.5).normalize()];
+        // Largest number below 1 drawn by `SimulationRng::next_f32`
+        let last = 1.0 - 1.0 / (1 << 24) as f32;
+        let samples = (0..10).map(|i| i as f32 / 10.0).chain([last]);
+        for forward in forwards {
+            for u in samples.clone() {
+                for v in samples.clone() {
+                    let direction = spread_direction(forward, spread, u, v);
+                    assert!(direction.is_normalized(), "{direction}");
+                    let angle = direction.angle_between(forward);
+                    assert!(angle <= spread + 1e-4, "{forward} {u} {v} {angle}");
+                }
+            }
+            // Whole cone is covered, from the axis to the edge
+            let axis = spread_direction(forward, spread, 0.0, 0.0);
+            assert!(axis.angle_between(forward) < 1e-3);
+            let edge = spread_direction(forward, spread, last, 0.0);
+            assert!((edge.angle_between(forward) - spread).abs() < 1e-3);
+        }
+    }
+
+    /// Directions of live bullets, ordered by entity
+    fn bullet_directions(app: &mut App) -> Vec<Vec3> {
+        let world = app.world_mut();
+        let mut bullets: Vec<_> = world
+            .query::<(Entity, &Bullet, &Transform)>()
+            .iter(world)
+            .filter(|(_, bullet, _)| bullet.is_live())
+            .map(|(entity, _, transform)| (entity, transform.rotation * Vec3::Y))
+            .collect();
+        bullets.sort_by_key(|(entity, _)| *entity);
+        bullets
+            .into_iter()
+            .map(|(_, direction)| direction)
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_spread() {
+        let fire = |seed| {
+            let mut app = weapon_app();
+            app.insert_resource(SimulationRng::new(seed))
+                .add_systems(FixedUpdate, hold_triggers.before(WeaponFireSet));
+            app.world_mut()
+                .spawn(ship(Vec3::ZERO))
+                .insert(ColliderMassProperties::Density(1000.0))
+                .with_child((Transform::default(), Weapon::new("autocannon")));
+            run(&mut app, 1.5);
+            bullet_directions(&mut app)
+        };
+
+        let directions = fire(7);
+        assert!(directions.len() > 5, "{}", directions.len());
+        assert_eq!(fire(7), directions);
+        assert_ne!(fire(8), directions);
+    }
+
+    #[test]
+    fn bloom_grows_with_sustained_fire_and_recovers_when_idle() {
+        let mut app = weapon_app();
+        app.add_systems(
+            FixedUpdate,
+            hold_triggers
+                .before(WeaponFireSet)
+                .run_if(|tick: Res<SimulationTick>| tick.0 < DEFAULT_TICK_RATE),
+        );
+        let weapon = app
+            .world_mut()
+            .spawn(ship(Vec3::ZERO))
+            .insert(ColliderMassProperties::Density(1000.0))
+            .with_child((Transform::default(), Weapon::new("autocannon")))
+            .id();
+        let weapon = app.world().get::<Children>(weapon).unwrap()[0];
+        let bloom = |app: &App| app.world().get::<Weapon>(weapon).unwrap().bloom;
+
+        run(&mut app, 1.0);
+
+        // Every shot of the autocannon adds 0.1 degrees, up to 1.2
+        let shots = bullet_directions(&mut app).len();
+        assert!(shots > 2, "{shots}");
+        let expected = (shots as f32 * 0.1).min(1.2).to_radians();
+        assert!((bloom(&app) - expected).abs() < 1e-5, "{}", bloom(&app));
+        // Later shots leave within the grown spread
+        let spread = 0.3f32.to_radians() + bloom(&app);
+        for direction in bullet_directions(&mut app) {
+            assert!(direction.angle_between(Vec3::NEG_Z) <= spread + 1e-4);
+        }
+
+        // Recovers 1.5 degrees per second
+        run(&mut app, 0.25);
+        let recovered = (expected - 0.375f32.to_radians()).max(0.0);
+        assert!((bloom(&app) - recovered).abs() < 1e-4, "{}", bloom(&app));
+        run(&mut app, 1.0);
+        assert_eq!(bloom(&app), 0.0);
+    }
+
+    #[test]
+    fn converged_barrels_meet_at_distance() {
+        // Blaster has no spread, so bolts leave exactly along the converged direction
+        for (convergence, distance) in [
+            (Convergence::at(100.0), 100.0),
+            (Convergence::at(100.0).with_target(0.2, 500.0), 60.0),
+        ] {
+            let mut app = weapon_app();
+            app.add_systems(FixedUpdate, hold_triggers.before(WeaponFireSet));
+            let world = app.world_mut();
+            world
+                .spawn((ship(Vec3::ZERO), Faction::Alliance.bundle()))
+                .insert(ColliderMassProperties::Density(1000.0))
+                .with_children(|ship| {
+                    for x in [-3.0, 3.0] {
+                        ship.spawn((
+                            Transform::from_xyz(x, 0.0, 0.0),
+                            Weapon::new("blaster").with_convergence(convergence),
+                        ));
+                    }
+                });
+            // Off the axis but inside the cone, so only its distance along the axis counts
+            world.spawn((
+                target(Vec3::new(5.0, 0.0, -60.0)),
+                Faction::Raiders.bundle(),
+            ));
+
+            app.update();
+
+            let world = app.world_mut();
+            let bolts: Vec<_> = world
+                .query::<(&Bullet, &Transform)>()
+                .iter(world)
+                .filter(|(bullet, _)| bullet.is_live())
+                .map(|(_, transform)| (transform.translation, transform.rotation * Vec3::Y))
+                .collect();
+            assert_eq!(bolts.len(), 2);
+            for (position, direction) in bolts {
+                // Where the bolt path crosses the ship axis
+                let crossing = position - direction * position.x / direction.x;
+                let expected = Vec3::new(0.0, 0.0, -distance);
+                assert!(crossing.distance(expected) < 0.01, "{crossing}");
+            }
+        }
+    }
+
+    fn catalog_error(weapon: &str) -> String {
+        let catalog: WeaponCatalog = ron::from_str(&format!(
+            "#![enable(unwrap_variant_newtypes)] (weapons: {{ \"broken\": {weapon} }})"
+        ))
+        .unwrap();
+        let mut meshes = Assets::<Mesh>::default();
+        let mut materials = Assets::<StandardMaterial>::default();
+        match WeaponTypes::from_catalog(&catalog, &mut meshes, &mut materials) {
+            Ok(_) => panic!("{weapon} is accepted"),
+            Err(error) => error,
+        }
+    }
+
+    fn gun(fields: &str, projectile: &str) -> String {
+        format!("Gun({fields} projectile: (color: (1.0, 1.0, 1.0), damage: 1.0, {projectile}))")
+    }
+
+    fn beam(fields: &str) -> String {
+        format!("Beam({fields} damage_per_second: 10.0, color: (1.0, 1.0, 1.0))")
+    }
+
+    fn launcher(fields: &str, missile: &str) -> String {
+        format!(
+            "Launcher({fields} lock_time: 1.0, lock_range: 500.0, missile: (color: (1.0, 1.0, 1.0), \
+             launch_speed: 10.0, thrust: 50.0, fuel: 5.0, turn_rate: 90.0, proximity: 3.0, \
+             lifetime: 10.0, damage: 10.0, {missile}))"
+        )
+    }
+
+    #[test]
+    fn invalid_catalog_entries_are_rejected() {
+        let projectile = "radius: 0.1, length: 1.0, speed: 100.0, lifetime: 1.0";
+        let missile = "radius: 0.2, length: 2.0, mass: 10.0";
+        let heat = |cooling: &str, capacity: f32, recover: f32| {
+            format!(
+                "rate_of_fire: 1.0, heat: Some((per_shot: 1.0, capacity: {capacity:?}, \
+                 cooling: {cooling}, recover: {recover:?})),"
+            )
+        };
+        for (weapon, expected) in [
+            (gun("rate_of_fire: 0.0,", projectile), "rate_of_fire 0"),
+            (gun("rate_of_fire: -2.0,", projectile), "rate_of_fire -2"),
+            (
+                gun("rate_of_fire: 1.0, spread: -0.5,", projectile),
+                "spread -0.5",
+            ),
+            (
+                gun(
+                    "rate_of_fire: 1.0,",
+                    "radius: 0.1, length: 1.0, speed: 0.0, lifetime: 1.0",
+                ),
+                "speed 0",
+            ),
+            (
+                gun(
+                    "rate_of_fire: 1.0,",
+                    "radius: 0.1, length: 1.0, speed: 100.0, lifetime: -1.0",
+                ),
+                "lifetime -1",
+            ),
+            (
+                gun(
+                    "rate_of_fire: 1.0,",
+                    "radius: 0.0, length: 1.0, speed: 100.0, lifetime: 1.0",
+                ),
+                "projectile radius 0",
+            ),
+            (
+                gun(
+                    "rate_of_fire: 1.0,",
+                    "radius: 0.5, length: 0.6, speed: 100.0, lifetime: 1.0",
+                ),
+                "projectile length 0.6",
+            ),
+            (
+                gun("rate_of_fire: 1.0,", &format!("{projectile}, mass: -1.0")),
+                "projectile mass -1",
+            ),
+            (
+                gun(&heat("Linear(10.0)", 0.0, 0.5), projectile),
+                "capacity 0",
+            ),
+            (
+                gun(&heat("Exponential(0.0)", 100.0, 0.5), projectile),
+                "half-life 0",
+            ),
+            (
+                gun(&heat("Linear(-5.0)", 100.0, 0.5), projectile),
+                "cooling rate -5",
+            ),
+            (
+                gun(&heat("Linear(10.0)", 100.0, -0.1), projectile),
+                "recover -0.1",
+            ),
+            (
+                gun(&heat("Linear(10.0)", 100.0, 1.5), projectile),
+                "recover 1.5",
+            ),
+            (
+                gun(
+                    "rate_of_fire: 1.0, burst: Some((shots: 0, pause: 1.0)),",
+                    projectile,
+                ),
+                "burst shots",
+            ),
+            (
+                gun(
+                    "rate_of_fire: 1.0, magazine: Some((size: 0, reload: 1.0)),",
+                    projectile,
+                ),
+                "magazine size",
+            ),
+            (beam("range: 0.0, width: 0.1,"), "beam range 0"),
+            (beam("range: 100.0, width: -0.1,"), "beam width -0.1"),
+            (
+                launcher("reload: 0.0, lock_angle: 10.0,", missile),
+                "launcher reload 0",
+            ),
+            (
+                launcher("reload: 1.0, lock_angle: 0.0,", missile),
+                "launcher lock_angle 0",
+            ),
+            (
+                launcher(
+                    "reload: 1.0, lock_angle: 10.0,",
+                    "radius: 0.5, length: 0.8, mass: 10.0",
+                ),
+                "missile length 0.8",
+            ),
+            (
+                launcher(
+                    "reload: 1.0, lock_angle: 10.0,",
+                    "radius: 0.2, length: 2.0, mass: 0.0",
+                ),
+                "missile mass 0",
+            ),
+        ] {
+            let error = catalog_error(&weapon);
+            assert!(error.starts_with("weapon \"broken\": "), "{error}");
+            assert!(error.contains(expected), "{error}");
         }
     }
 }